@@ -0,0 +1,79 @@
+//! Trigger search over every channel of the bundled songs, pruned and unpruned side by side.
+//! Renders are cached in the system temp dir, so only the first run renders. Run with
+//! `cargo bench --features bench`; to compare two revisions, add
+//! `-- --save-baseline before` on one and `-- --baseline before` on the other.
+
+use std::{hint::black_box, path::Path};
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use oscilloscope::{
+    channel::{build_channels, EndBehavior, FrameRate, TriggerMode},
+    midi::MidiResource,
+    source::{get_render, render_cache_path, Arrangement, InstrumentKind},
+};
+use soundmaker::prelude::*;
+
+const SAMPLE_RATE: f64 = 44100.0;
+const PIANO: InstrumentKind = InstrumentKind {
+    name: "piano",
+    version: 1,
+};
+const SONGS: [&str; 4] = [
+    "castle.mid",
+    "Chill Beats.mid",
+    "Dream Of The Ocean.mid",
+    "spring_rain.mid",
+];
+
+fn trigger_frames(c: &mut Criterion) {
+    let mut group = c.benchmark_group("trigger_frames");
+    group.sample_size(10);
+    for song in SONGS {
+        let path = Path::new("./assets").join(song);
+        let (bytes, midi) = MidiResource::read(&path).unwrap();
+        let mut arrangement = Arrangement::new();
+        for (i, info) in midi.track_infos.iter().enumerate() {
+            arrangement.add_instrument(info.display_name(i), PIANO, &piano(), 1.0, 0.0);
+        }
+        arrangement.daw.set_midi_bytes(&bytes);
+        let cache = render_cache_path(&path, &bytes, &arrangement.instruments, SAMPLE_RATE);
+        let cache = std::env::temp_dir()
+            .join("oscilloscope-bench")
+            .join(cache.file_name().unwrap());
+        let (wave, _) = get_render(&mut arrangement.daw, SAMPLE_RATE, cache);
+        let channels = build_channels(
+            &*wave,
+            Some(&midi),
+            TriggerMode::Midi,
+            FrameRate::Display,
+            EndBehavior::Stop,
+        );
+
+        group.bench_with_input(
+            BenchmarkId::new("pruned", song),
+            &channels,
+            |b, channels| {
+                b.iter(|| {
+                    for channel in channels {
+                        black_box(channel.trigger_frames(SAMPLE_RATE, |_| {}));
+                    }
+                })
+            },
+        );
+        group.bench_with_input(
+            BenchmarkId::new("unpruned", song),
+            &channels,
+            |b, channels| {
+                b.iter(|| {
+                    for channel in channels {
+                        black_box(channel.trigger_frames_unpruned(SAMPLE_RATE, |_| {}));
+                    }
+                })
+            },
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, trigger_frames);
+criterion_main!(benches);
@@ -1,21 +1,200 @@
 use std::path::PathBuf;
 
-use bevy::{prelude::*, window::PresentMode};
+use bevy::{
+    prelude::*,
+    window::{close_on_esc, PresentMode},
+};
 use bevy_prototype_lyon::prelude::*;
-use soundmaker::daw::{render_daw, RenderedAudio, DAW};
-
-use crate::{
+use oscilloscope::{
+    cache::MappedSource,
+    channel::{build_channels, EndBehavior, FrameRate, Gain, TriggerMode},
+    cursors::CursorsPlugin,
+    error::{AppError, ErrorLog, ErrorOverlayPlugin, ErrorSource},
     fps::FpsDiagnosticsPlugin,
-    wave::{WavePlugin, WaveResource},
+    graticule::GraticulePlugin,
+    live::{LiveConfig, LivePlugin},
+    midi::MidiResource,
+    pitch::PitchPlugin,
+    playlist::{Playlist, PlaylistEntry, PlaylistPlugin},
+    reload::{Project, ReloadPlugin},
+    report::TriggerReport,
+    source::{daw_channel_names, get_render, render_cache_path, BufferedSource},
+    stems::load_stems,
+    trace::TraceRenderer,
+    OscilloscopePlugin,
 };
 
-pub fn run(mut daw: DAW, sample_rate: f64) {
-    let render = get_render(&mut daw, sample_rate, PathBuf::from("./output/render.bin"));
+/// Display settings shared by every mode that shows a finite song.
+#[derive(Default)]
+pub struct ScopeOptions {
+    pub trigger_mode: TriggerMode,
+    pub frame_rate: FrameRate,
+    pub end_behavior: EndBehavior,
+    pub gain: Gain,
+    pub trace_renderer: TraceRenderer,
+    pub export: Option<PathBuf>,
+    pub recording: Option<PathBuf>,
+    /// Why there is no audio output. The scope still runs, silently, and shows this error.
+    pub audio_error: Option<AppError>,
+}
+
+impl ScopeOptions {
+    fn into_app(self, scope: OscilloscopePlugin) -> App {
+        let scope = scope
+            .with_trigger_mode(self.trigger_mode)
+            .with_frame_rate(self.frame_rate)
+            .with_end_behavior(self.end_behavior)
+            .with_gain(self.gain)
+            .with_trace_renderer(self.trace_renderer)
+            .with_playback_keys();
+        let scope = match self.audio_error {
+            Some(_) => scope,
+            None => scope.with_audio_output(),
+        };
+        let scope = match self.recording {
+            Some(path) => scope.with_recording(path),
+            None => scope,
+        };
+        let scope = match self.export {
+            Some(dir) => scope.with_export(dir),
+            None => scope,
+        };
+
+        let mut app = build_app(scope);
+        if let Some(e) = self.audio_error {
+            app.world.resource_mut::<ErrorLog>().push(e);
+        }
+        app
+    }
+}
+
+pub fn run(project: Project, sample_rate: f64, options: ScopeOptions) {
+    let (arrangement, midi) = match project.load() {
+        Ok(loaded) => loaded,
+        Err(e) => return run_error(e),
+    };
+    let daw = &arrangement.daw;
+    let cache = render_cache_path(&project.path, &midi, &arrangement.instruments, sample_rate);
+    let render = MappedSource::open_at(&cache, daw_channel_names(daw), sample_rate).ok();
+    let midi = MidiResource::parse(&midi)
+        .map_err(|e| eprintln!("Could not parse MIDI, notes are unavailable: {e}"))
+        .ok();
+    let mismatch = midi
+        .as_ref()
+        .and_then(|midi| midi.check_track_count(daw.channel_count).err());
+
+    // Without a cached render, the window opens empty and the reload task renders in the background.
+    let render_on_start = render.is_none();
+    let scope = match render {
+        Some(render) => OscilloscopePlugin::new(render),
+        None => OscilloscopePlugin::new(BufferedSource::new(
+            Default::default(),
+            Vec::new(),
+            Vec::new(),
+            sample_rate,
+        )),
+    };
+    let mut app = options.into_app(scope);
+    // Tagged as a reload error, so a reload that fixes the project takes it back.
+    if let Some(e) = mismatch {
+        app.world
+            .resource_mut::<ErrorLog>()
+            .push_from(ErrorSource::Reload, e);
+    }
+    if let Some(midi) = midi {
+        app.insert_resource(midi);
+    }
+    app.add_plugins(ReloadPlugin {
+        project,
+        sample_rate,
+        render_on_start,
+    });
+    app.run();
+}
+
+/// Runs the trigger over the whole song without opening a window and prints how steady it was.
+pub fn run_report(project: Project, sample_rate: f64, options: ScopeOptions) {
+    if let Some(e) = options.audio_error {
+        eprintln!("{e}");
+    }
+    let (mut arrangement, midi) = match project.load() {
+        Ok(loaded) => loaded,
+        Err(e) => return eprintln!("{e}"),
+    };
+    let cache = render_cache_path(&project.path, &midi, &arrangement.instruments, sample_rate);
+    let (source, warning) = get_render(&mut arrangement.daw, sample_rate, cache);
+    if let Some(warning) = warning {
+        eprintln!("{warning}");
+    }
+
+    let midi = MidiResource::parse(&midi).ok();
+    let channels = build_channels(
+        &*source,
+        midi.as_ref(),
+        options.trigger_mode,
+        options.frame_rate,
+        options.end_behavior,
+    );
+    print!("{}", TriggerReport::new(&channels, sample_rate));
+}
+
+pub fn run_stems(dir: PathBuf, options: ScopeOptions) {
+    match load_stems(&dir) {
+        Ok(source) => options.into_app(OscilloscopePlugin::new(source)).run(),
+        Err(e) => run_error(AppError::Audio(dir, e)),
+    }
+}
 
-    App::new()
-        .insert_resource(ClearColor(Color::hex("282C34").unwrap()))
-        .insert_resource(WaveResource::from((render, daw)))
-        .add_plugins(WavePlugin(sample_rate))
+pub fn run_playlist(entries: Vec<PlaylistEntry>, sample_rate: f64, options: ScopeOptions) {
+    if entries.is_empty() {
+        return run_error(AppError::EmptyPlaylist);
+    }
+
+    // The window opens empty and every song is loaded, and rendered if needed, in the background.
+    // The playlist moves on when a song ends, so the end behavior doesn't apply here.
+    let options = ScopeOptions {
+        end_behavior: EndBehavior::Stop,
+        ..options
+    };
+    let mut app = options.into_app(OscilloscopePlugin::new(BufferedSource::new(
+        Default::default(),
+        Vec::new(),
+        Vec::new(),
+        sample_rate,
+    )));
+    app.insert_resource(Playlist::new(entries))
+        .add_plugins(PlaylistPlugin);
+    app.run();
+}
+
+pub fn run_live(config: LiveConfig, options: ScopeOptions) {
+    let mut app = base_app();
+    app.insert_resource(options.trace_renderer)
+        .add_plugins(ShapePlugin)
+        .add_plugins(GraticulePlugin)
+        .add_plugins(CursorsPlugin)
+        .add_plugins(PitchPlugin)
+        .add_plugins(ErrorOverlayPlugin)
+        .add_plugins(LivePlugin(config));
+    app.run();
+}
+
+pub fn run_error(error: AppError) {
+    let mut app = base_app();
+    app.add_plugins(ErrorOverlayPlugin);
+    app.world.resource_mut::<ErrorLog>().push(error);
+    app.run();
+}
+
+fn build_app(scope: OscilloscopePlugin) -> App {
+    let mut app = base_app();
+    app.add_plugins(scope);
+    app
+}
+
+fn base_app() -> App {
+    let mut app = App::new();
+    app.insert_resource(ClearColor(Color::hex("282C34").unwrap()))
         .add_plugins(DefaultPlugins.set(WindowPlugin {
             primary_window: Some(Window {
                 title: "Oscilloscope".to_string(),
@@ -24,20 +203,12 @@ pub fn run(mut daw: DAW, sample_rate: f64) {
             }),
             ..default()
         }))
-        .add_plugins(ShapePlugin)
         .add_plugins(FpsDiagnosticsPlugin)
         .add_systems(Startup, setup)
-        .run();
+        .add_systems(Update, close_on_esc);
+    app
 }
 
 fn setup(mut commands: Commands) {
     commands.spawn(Camera2dBundle::default());
 }
-
-fn get_render(daw: &mut DAW, sample_rate: f64, file_path: PathBuf) -> RenderedAudio {
-    RenderedAudio::load(file_path.clone()).unwrap_or_else(|_| {
-        let render = render_daw(daw, sample_rate);
-        render.save(file_path).unwrap();
-        render
-    })
-}
@@ -0,0 +1,337 @@
+//! Render cache: a fixed header, then for every channel and finally the master its stereo frames
+//! followed by its mono mixdown, all as little-endian f32. The file is memory-mapped, so opening a
+//! render of any length is instant and pages are only read from disk once they are touched.
+
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    marker::PhantomData,
+    ops::Range,
+    path::Path,
+    sync::Arc,
+};
+
+use memmap2::Mmap;
+
+use crate::{
+    error::AppError,
+    source::{AudioSource, Frame, SampleBuffer, SampleStorage},
+};
+
+const MAGIC: [u8; 4] = *b"OSCR";
+const VERSION: u32 = 1;
+const HEADER_LEN: usize = 32;
+const BYTES_PER_FRAME: usize = std::mem::size_of::<Frame>() + std::mem::size_of::<f32>();
+
+struct Header {
+    channel_count: usize,
+    sample_rate: f64,
+    frames: usize,
+}
+
+impl Header {
+    fn to_bytes(&self) -> [u8; HEADER_LEN] {
+        let mut bytes = [0; HEADER_LEN];
+        bytes[0..4].copy_from_slice(&MAGIC);
+        bytes[4..8].copy_from_slice(&VERSION.to_le_bytes());
+        bytes[8..12].copy_from_slice(&(self.channel_count as u32).to_le_bytes());
+        bytes[16..24].copy_from_slice(&self.sample_rate.to_le_bytes());
+        bytes[24..32].copy_from_slice(&(self.frames as u64).to_le_bytes());
+        bytes
+    }
+    fn parse(bytes: &[u8]) -> Result<Self, String> {
+        let Some(bytes) = bytes.get(..HEADER_LEN) else {
+            return Err("file is shorter than the header".to_string());
+        };
+        if bytes[0..4] != MAGIC {
+            return Err("not a render cache".to_string());
+        }
+        let version = u32::from_le_bytes(field(bytes, 4));
+        if version != VERSION {
+            return Err(format!("cache version {version}, expected {VERSION}"));
+        }
+        Ok(Self {
+            channel_count: u32::from_le_bytes(field(bytes, 8)) as usize,
+            sample_rate: f64::from_le_bytes(field(bytes, 16)),
+            frames: u64::from_le_bytes(field(bytes, 24)) as usize,
+        })
+    }
+    fn file_len(&self) -> Option<usize> {
+        (self.channel_count + 1)
+            .checked_mul(self.frames)?
+            .checked_mul(BYTES_PER_FRAME)?
+            .checked_add(HEADER_LEN)
+    }
+}
+
+fn field<const N: usize>(bytes: &[u8], at: usize) -> [u8; N] {
+    bytes[at..at + N].try_into().unwrap()
+}
+
+/// Plain f32 data that any bit pattern in the file is valid for.
+trait MappedSample: Copy + Send + Sync + 'static {}
+
+impl MappedSample for f32 {}
+impl MappedSample for Frame {}
+
+/// `len` samples at `offset` bytes into a shared mapping.
+struct Plane<T> {
+    map: Arc<Mmap>,
+    offset: usize,
+    len: usize,
+    sample: PhantomData<T>,
+}
+
+impl<T: MappedSample> Plane<T> {
+    fn buffer(map: &Arc<Mmap>, offset: usize, len: usize) -> SampleBuffer<T> {
+        SampleBuffer::new(Self {
+            map: map.clone(),
+            offset,
+            len,
+            sample: PhantomData,
+        })
+    }
+}
+
+impl<T: MappedSample> AsRef<[T]> for Plane<T> {
+    fn as_ref(&self) -> &[T] {
+        // SAFETY: `open` checked that the plane lies inside the mapping. Mappings are page aligned
+        // and every offset is a multiple of four, which is all f32 and `[f32; 2]` need, and any
+        // bit pattern is a valid f32. The cache is only ever replaced by renaming a new file over
+        // it, so the mapped file itself is never written to.
+        unsafe {
+            std::slice::from_raw_parts(self.map.as_ptr().add(self.offset).cast::<T>(), self.len)
+        }
+    }
+}
+
+impl<T: MappedSample> SampleStorage<T> for Plane<T> {
+    #[cfg(unix)]
+    fn release(&self, range: Range<usize>) {
+        let size = std::mem::size_of::<T>();
+        let end = range.end.min(self.len);
+        let start = range.start.min(end);
+        // SAFETY: the mapping is private and read-only and its file is never written to, so
+        // dropping pages only means later reads fault them back in from the file unchanged.
+        let _ = unsafe {
+            self.map.unchecked_advise_range(
+                memmap2::UncheckedAdvice::DontNeed,
+                self.offset + start * size,
+                (end - start) * size,
+            )
+        };
+    }
+}
+
+/// A render cache opened with `open`. Channel `channel_count()` is the master mix.
+pub struct MappedSource {
+    planes: Vec<(SampleBuffer<Frame>, SampleBuffer<f32>)>,
+    channel_names: Vec<String>,
+    sample_rate: f64,
+}
+
+impl MappedSource {
+    pub fn open(path: &Path, channel_names: Vec<String>) -> Result<Self, AppError> {
+        let unreadable = |e: String| AppError::RenderUnreadable(path.to_path_buf(), e);
+        if cfg!(target_endian = "big") {
+            return Err(unreadable("render caches are little-endian".to_string()));
+        }
+        let file = File::open(path).map_err(|e| unreadable(e.to_string()))?;
+        // SAFETY: see `Plane::as_ref`; the file is never modified in place.
+        let map = unsafe { Mmap::map(&file) }.map_err(|e| unreadable(e.to_string()))?;
+        let header = Header::parse(&map).map_err(unreadable)?;
+        if header.file_len() != Some(map.len()) {
+            return Err(unreadable("file is truncated".to_string()));
+        }
+
+        let map = Arc::new(map);
+        let frames = header.frames;
+        let planes = (0..=header.channel_count)
+            .map(|i| {
+                let offset = HEADER_LEN + i * frames * BYTES_PER_FRAME;
+                let mono_offset = offset + frames * std::mem::size_of::<Frame>();
+                (
+                    Plane::buffer(&map, offset, frames),
+                    Plane::buffer(&map, mono_offset, frames),
+                )
+            })
+            .collect();
+        Ok(Self {
+            planes,
+            channel_names,
+            sample_rate: header.sample_rate,
+        })
+    }
+    /// Like `open`, but rejects a cache rendered at any other rate than `sample_rate`.
+    pub fn open_at(
+        path: &Path,
+        channel_names: Vec<String>,
+        sample_rate: f64,
+    ) -> Result<Self, AppError> {
+        let source = Self::open(path, channel_names)?;
+        if source.sample_rate != sample_rate {
+            return Err(AppError::RenderUnreadable(
+                path.to_path_buf(),
+                format!(
+                    "rendered at {} Hz, expected {sample_rate} Hz",
+                    source.sample_rate
+                ),
+            ));
+        }
+        Ok(source)
+    }
+}
+
+impl AudioSource for MappedSource {
+    fn sample_rate(&self) -> f64 {
+        self.sample_rate
+    }
+    fn channel_count(&self) -> usize {
+        self.planes.len() - 1
+    }
+    fn channel_name(&self, index: usize) -> String {
+        self.channel_names
+            .get(index)
+            .cloned()
+            .unwrap_or_else(|| format!("Channel {}", index + 1))
+    }
+    fn samples(&self, index: usize) -> Option<&SampleBuffer<Frame>> {
+        self.planes.get(index).map(|p| &p.0)
+    }
+    fn mono(&self, index: usize) -> SampleBuffer<f32> {
+        self.planes
+            .get(index)
+            .map(|p| p.1.clone())
+            .unwrap_or_default()
+    }
+}
+
+/// Writes every channel of `source` as a render cache. The file is written next to `path` and
+/// renamed into place, so caches that are already mapped keep their old contents.
+pub fn write_cache(source: &dyn AudioSource, path: &Path) -> Result<(), AppError> {
+    let not_writable =
+        |e: io::Error| AppError::OutputNotWritable(path.to_path_buf(), e.to_string());
+    let header = Header {
+        channel_count: source.channel_count(),
+        sample_rate: source.sample_rate(),
+        frames: source.master().len(),
+    };
+
+    let temp_path = path.with_extension("tmp");
+    let mut writer = BufWriter::new(File::create(&temp_path).map_err(not_writable)?);
+    writer.write_all(&header.to_bytes()).map_err(not_writable)?;
+    for i in 0..=header.channel_count {
+        // Shorter channels are padded with silence so every plane has the same length.
+        let samples = source.samples(i).cloned().unwrap_or_default();
+        let stereo = samples.iter().chain(std::iter::repeat(&[0.0; 2]));
+        write_samples(&mut writer, stereo.take(header.frames).flatten().copied())
+            .map_err(not_writable)?;
+        let mono = source.mono(i);
+        let mono = mono.iter().copied().chain(std::iter::repeat(0.0));
+        write_samples(&mut writer, mono.take(header.frames)).map_err(not_writable)?;
+    }
+    writer
+        .into_inner()
+        .map_err(|e| not_writable(e.into_error()))?;
+    std::fs::rename(&temp_path, path).map_err(not_writable)
+}
+
+fn write_samples(writer: &mut impl Write, samples: impl Iterator<Item = f32>) -> io::Result<()> {
+    for sample in samples {
+        writer.write_all(&sample.to_le_bytes())?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        source::BufferedSource,
+        test_signals::{noise, sine, Rng, SAMPLE_RATE},
+    };
+
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("oscilloscope-{}-{name}", std::process::id()))
+    }
+
+    fn source() -> BufferedSource {
+        let mut rng = Rng::new(11);
+        let stereo = |left: Vec<f32>, right: Vec<f32>| -> SampleBuffer<Frame> {
+            let frames: Vec<Frame> = left.into_iter().zip(right).map(|(l, r)| [l, r]).collect();
+            frames.into()
+        };
+        BufferedSource::new(
+            stereo(sine(440.0, 5000), noise(&mut rng, 5000)),
+            vec![
+                stereo(sine(220.0, 5000), sine(330.0, 5000)),
+                stereo(noise(&mut rng, 3000), noise(&mut rng, 3000)),
+            ],
+            vec!["A".into(), "B".into()],
+            SAMPLE_RATE,
+        )
+    }
+
+    #[test]
+    fn cache_round_trips_every_channel() {
+        let path = temp_path("round-trip");
+        let source = source();
+        write_cache(&source, &path).unwrap();
+        let mapped = MappedSource::open(&path, source.channel_names.clone()).unwrap();
+
+        assert_eq!(mapped.sample_rate(), SAMPLE_RATE);
+        assert_eq!(mapped.channel_count(), 2);
+        assert_eq!(mapped.channel_name(1), "B");
+        assert_eq!(**mapped.samples(0).unwrap(), *source.channels[0]);
+        assert_eq!(*mapped.master(), *source.master);
+        assert_eq!(*mapped.mono(2), *source.mono(2));
+        assert!(mapped.samples(3).is_none());
+        assert!(mapped.mono(3).is_empty());
+
+        // Shorter channels are padded with silence to the length of the master.
+        let short = mapped.samples(1).unwrap();
+        assert_eq!(short.len(), 5000);
+        assert_eq!(short[..3000], *source.channels[1]);
+        assert!(short[3000..].iter().all(|&s| s == [0.0; 2]));
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn released_samples_read_back_unchanged() {
+        let path = temp_path("release");
+        let source = source();
+        write_cache(&source, &path).unwrap();
+        let mapped = MappedSource::open(&path, Vec::new()).unwrap();
+
+        let master = mapped.master();
+        master.release(0..master.len());
+        master.release(4000..usize::MAX);
+        assert_eq!(*master, *source.master);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn caches_at_another_sample_rate_are_rejected() {
+        let path = temp_path("sample-rate");
+        write_cache(&source(), &path).unwrap();
+
+        assert!(MappedSource::open_at(&path, Vec::new(), SAMPLE_RATE).is_ok());
+        assert!(MappedSource::open_at(&path, Vec::new(), 48000.0).is_err());
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn damaged_caches_are_rejected() {
+        let path = temp_path("damaged");
+        write_cache(&source(), &path).unwrap();
+        let bytes = std::fs::read(&path).unwrap();
+
+        std::fs::write(&path, &bytes[..bytes.len() - 4]).unwrap();
+        assert!(MappedSource::open(&path, Vec::new()).is_err());
+        std::fs::write(&path, &bytes[..10]).unwrap();
+        assert!(MappedSource::open(&path, Vec::new()).is_err());
+        std::fs::write(&path, b"not a cache at all, just some bytes").unwrap();
+        assert!(MappedSource::open(&path, Vec::new()).is_err());
+        std::fs::remove_file(path).unwrap();
+    }
+}
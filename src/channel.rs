@@ -1,4 +1,11 @@
-use std::time::Instant;
+use std::{
+    borrow::Cow,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Instant,
+};
 
 use bevy::{
     ecs::system::CommandQueue,
@@ -9,193 +16,693 @@ use bevy_prototype_lyon::prelude::*;
 use rayon::iter::{IntoParallelRefMutIterator, ParallelIterator};
 
 use crate::{
-    line::samples_to_path,
-    wave::{start_playback, PlaybackResource, WaveResource},
+    error::ErrorLog,
+    export::Export,
+    graticule::spawn_graticule,
+    line::{samples_to_line_strip, samples_to_path},
+    loading::{spawn_loading_screen, ProgressBar},
+    meter::spawn_meter,
+    midi::{lowest_active_note, MidiResource, Note},
+    piano_roll::spawn_note_lane,
+    plugin::Overlays,
+    source::{AudioSource, Frame, SampleBuffer},
+    stereo::spawn_goniometer,
+    trace::TraceMesh,
+    wave::{record, start_playback, start_silent, PlaybackOutput, PlaybackResource, WaveResource},
 };
 
+const DEFAULT_SEARCH_WINDOW: usize = 800;
+const MIN_SEARCH_WINDOW: usize = 32;
+
+/// Picks the crossing whose window best matches the reference, as its score and index.
+type CrossingSearch =
+    fn(&ChannelData, &[f32], usize, &mut dyn Iterator<Item = usize>) -> Option<(f32, usize)>;
+
+#[derive(Resource, Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum TriggerMode {
+    #[default]
+    Fixed,
+    Midi,
+}
+
+impl TriggerMode {
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value {
+            "fixed" => Ok(TriggerMode::Fixed),
+            "midi" => Ok(TriggerMode::Midi),
+            other => Err(format!("trigger mode must be fixed or midi, got {other}")),
+        }
+    }
+}
+
+/// `Display` triggers on the fly between precomputed anchor frames, so every refresh rate gets
+/// its own trigger points. `Fixed` snaps to a frame grid, for exporting at a standard video rate.
+#[derive(Resource, Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum FrameRate {
+    #[default]
+    Display,
+    Fixed(u32),
+}
+
+impl FrameRate {
+    pub const EXPORT_RATES: [u32; 4] = [24, 30, 50, 60];
+
+    pub fn parse(value: &str) -> Result<Self, String> {
+        value
+            .parse()
+            .ok()
+            .filter(|fps| Self::EXPORT_RATES.contains(fps))
+            .map(FrameRate::Fixed)
+            .ok_or(format!("frame rate must be 24, 30, 50 or 60, got {value}"))
+    }
+    pub fn precompute_fps(self) -> f64 {
+        match self {
+            FrameRate::Display => 60.0,
+            FrameRate::Fixed(fps) => fps as f64,
+        }
+    }
+}
+
+/// What the scope shows once playback reaches the end of the song.
+#[derive(Resource, Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum EndBehavior {
+    /// Fall silent, like a real scope after the signal stops.
+    #[default]
+    Stop,
+    /// Start over from the beginning.
+    Loop,
+    /// Keep showing the last frame.
+    Hold,
+}
+
+impl EndBehavior {
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value {
+            "stop" => Ok(EndBehavior::Stop),
+            "loop" => Ok(EndBehavior::Loop),
+            "hold" => Ok(EndBehavior::Hold),
+            other => Err(format!(
+                "end behavior must be stop, loop or hold, got {other}"
+            )),
+        }
+    }
+}
+
+/// Vertical scale of every trace, so full scale is ±1 / gain.
+#[derive(Resource, Clone, Copy, PartialEq, Debug)]
+pub struct Gain(pub f64);
+
+impl Default for Gain {
+    fn default() -> Self {
+        Self(1.0)
+    }
+}
+
+impl Gain {
+    pub fn parse(value: &str) -> Result<Self, String> {
+        value
+            .parse()
+            .ok()
+            .filter(|&gain: &f64| gain > 0.0 && gain.is_finite())
+            .map(Gain)
+            .ok_or(format!("gain must be a positive number, got {value}"))
+    }
+}
+
+/// A channel's mono samples, shared with the source they came from, or for live input a window
+/// that is refilled in place.
+enum Samples {
+    Shared(SampleBuffer<f32>),
+    Live(Vec<f32>),
+}
+
+impl std::ops::Deref for Samples {
+    type Target = [f32];
+
+    fn deref(&self) -> &[f32] {
+        match self {
+            Samples::Shared(samples) => samples,
+            Samples::Live(samples) => samples,
+        }
+    }
+}
+
+/// A channel's mono samples. The zero padding of `2 * buffer_size` on either side, so the first
+/// and last frames have a full window, is logical: indices count from the start of the leading
+/// padding, but only the samples themselves are stored, shared with the source they came from.
 #[derive(Component)]
 pub struct ChannelData {
-    data: Vec<f64>,
-    index: usize,
-    frame_indices: Vec<usize>,
+    data: Samples,
+    pub index: usize,
+    frame_indices: Vec<f64>,
     pub position: Rect,
     pub buffer_size: usize,
     pub target_fps: f64,
+    pub gain: f64,
     pub name: String,
+    pub notes: Vec<Note>,
+    pub trigger_mode: TriggerMode,
+    pub frame_rate: FrameRate,
+    pub end_behavior: EndBehavior,
+    pub progress: Arc<AtomicUsize>,
+    sample_rate: Option<f64>,
+    shown_index: f64,
+    prev_index: usize,
 }
 
 impl ChannelData {
     pub fn new(
-        data: &[(f64, f64)],
+        data: SampleBuffer<f32>,
         index: usize,
         name: String,
         position: Rect,
         buffer_size: usize,
         target_fps: f64,
     ) -> Self {
+        let buffer_size = buffer_size.max(1);
         Self {
-            data: vec![0.0; buffer_size * 2]
-                .into_iter()
-                .chain(data.into_iter().map(|x| (x.0 + x.1) / 2.0))
-                .chain(vec![0.0; buffer_size * 2])
-                .collect(),
+            data: Samples::Shared(data),
             index,
             frame_indices: Vec::new(),
             position,
             buffer_size,
             name,
             target_fps,
+            gain: 1.0,
+            notes: Vec::new(),
+            trigger_mode: TriggerMode::Fixed,
+            frame_rate: FrameRate::Display,
+            end_behavior: EndBehavior::Stop,
+            progress: Arc::new(AtomicUsize::new(0)),
+            sample_rate: None,
+            shown_index: (buffer_size * 2) as f64,
+            prev_index: buffer_size * 2,
         }
     }
+    pub fn from_source(
+        source: &dyn AudioSource,
+        index: usize,
+        position: Rect,
+        buffer_size: usize,
+        target_fps: f64,
+    ) -> Self {
+        let name = if index == source.channel_count() {
+            "Master".to_string()
+        } else {
+            source.channel_name(index)
+        };
+        let data = source.mono(index);
+        Self::new(data, index, name, position, buffer_size, target_fps)
+    }
+    pub fn frame_count(&self, sample_rate: f64) -> usize {
+        // Rounding can put the estimate a frame off, so settle it on the frame starts themselves.
+        let samples = self.len().saturating_sub(2 * self.buffer_size);
+        let mut last = (samples as f64 / sample_rate * self.target_fps) as usize;
+        while last > 0 && self.frame_start(last, sample_rate) > self.len() {
+            last -= 1;
+        }
+        while self.frame_start(last + 1, sample_rate) <= self.len() {
+            last += 1;
+        }
+        // Every frame up to the end of the padded data, plus the final silent frame.
+        last + 2
+    }
+    /// Where the trigger search for `frame` starts, scanning backwards from there.
+    fn frame_start(&self, frame: usize, sample_rate: f64) -> usize {
+        2 * self.buffer_size + (sample_rate * self.frame_time(frame)) as usize
+    }
+    fn frame_time(&self, frame: usize) -> f64 {
+        frame as f64 / self.target_fps
+    }
     pub fn precompute_indices(&mut self, sample_rate: f64) {
+        let start_time = Instant::now();
+        let indices = self.trigger_frames(sample_rate, |_| {});
+        info!(
+            "Finished precomputing {} in {:.2}s",
+            self.name,
+            start_time.elapsed().as_secs_f32()
+        );
+        self.frame_indices = indices;
+        self.sample_rate = Some(sample_rate);
+    }
+    /// Triggers every frame of the channel. `inspect` gets each frame's alignment error, the
+    /// mean absolute difference to the previous frame, or `None` if no crossing was found.
+    pub fn trigger_frames(&self, sample_rate: f64, inspect: impl FnMut(Option<f64>)) -> Vec<f64> {
+        self.trigger_frames_with(Self::best_crossing, sample_rate, inspect)
+    }
+    /// `trigger_frames` with every candidate scored in full, to check and benchmark it against.
+    #[cfg(any(test, feature = "bench"))]
+    pub fn trigger_frames_unpruned(
+        &self,
+        sample_rate: f64,
+        inspect: impl FnMut(Option<f64>),
+    ) -> Vec<f64> {
+        self.trigger_frames_with(Self::best_crossing_unpruned, sample_rate, inspect)
+    }
+    fn trigger_frames_with(
+        &self,
+        search: CrossingSearch,
+        sample_rate: f64,
+        mut inspect: impl FnMut(Option<f64>),
+    ) -> Vec<f64> {
         let mut indices = Vec::new();
-        let secs_per_frame = 1.0 / self.target_fps;
         let mut i = 0;
-        println!("Precomputing {}...", self.name);
-        let start_time = Instant::now();
         let mut prev_index = 2 * self.buffer_size;
         loop {
-            let passed_time = i as f64 * secs_per_frame;
-            let index = 2 * self.buffer_size + (sample_rate * passed_time) as usize;
-            if index > self.data.len() {
-                indices.push(self.data.len()); // Last Frame is just zeros
+            let passed_time = self.frame_time(i);
+            let index = self.frame_start(i, sample_rate);
+            if index > self.len() {
+                indices.push(self.len() as f64); // Last Frame is just zeros
                 break;
             }
 
-            let best_i = self.find_by_comp(800, index, &mut prev_index);
-            let clamped_i = best_i.clamp(self.buffer_size * 2, self.data.len());
-            indices.push(clamped_i);
+            let (search_window, compare_len) = self.trigger_window(passed_time, sample_rate);
+            let (best_i, error) =
+                self.find_by_comp(search, search_window, compare_len, index, &mut prev_index);
+            inspect(error);
+            indices.push(self.clamp_end(best_i));
             i += 1;
+            self.progress.store(i, Ordering::Relaxed);
         }
-        println!(
-            "Finished precomputing {} in {:.2}s",
-            self.name,
-            start_time.elapsed().as_secs_f32()
-        );
-        self.frame_indices = indices;
+        self.progress.store(indices.len(), Ordering::Relaxed);
+        indices
     }
-    fn find_by_comp(&mut self, samples_per_frame: usize, index: usize, prev: &mut usize) -> usize {
-        let data_diff = |i: usize, j: usize| -> f64 {
-            (1..=self.buffer_size)
-                .map(|x| (self.data[i - x] - self.data[j - x]).abs())
-                .sum()
-        };
-
-        let zeros = (0..samples_per_frame).filter_map(|x| {
-            let i = index - x;
-            let val = self.data[i];
-            if val >= 0.0 && self.data[i - 1] < 0.0 {
-                Some(i)
-            } else {
-                None
+    fn trigger_window(&self, time: f64, sample_rate: f64) -> (usize, usize) {
+        if self.trigger_mode == TriggerMode::Midi {
+            if let Some(note) = lowest_active_note(&self.notes, time) {
+                let period = sample_rate / note.frequency();
+                let search_window =
+                    ((2.0 * period) as usize).clamp(MIN_SEARCH_WINDOW, self.buffer_size);
+                let cycles = (self.buffer_size as f64 / period).floor().max(1.0);
+                let compare_len = ((cycles * period) as usize).clamp(1, self.buffer_size);
+                return (search_window, compare_len);
             }
-        });
+        }
+        (DEFAULT_SEARCH_WINDOW, self.buffer_size)
+    }
+    fn find_by_comp(
+        &self,
+        search: CrossingSearch,
+        samples_per_frame: usize,
+        compare_len: usize,
+        index: usize,
+        prev: &mut usize,
+    ) -> (f64, Option<f64>) {
+        let index = index.min(self.len().saturating_sub(1));
+        let reference = (*prev).clamp(compare_len, self.len());
+        let reference_window = self.span(reference - compare_len, reference);
 
-        let best = zeros
-            .map(|x| {
-                let score = data_diff(*prev, x);
-                (score, x)
-            })
-            .min_by(|a, b| a.0.total_cmp(&b.0));
+        // Candidates need a full comparison window and a sample before them.
+        let lowest = compare_len.max(1);
+        let best = if index >= lowest {
+            let start = index
+                .saturating_sub(samples_per_frame.saturating_sub(1))
+                .max(lowest);
+            let scan = self.span(start - 1, index + 1);
+            let mut crossings = (start..=index)
+                .rev()
+                .filter(|&i| scan[i - start + 1] >= 0.0 && scan[i - start] < 0.0);
+            search(self, &reference_window, compare_len, &mut crossings)
+        } else {
+            None
+        };
 
-        if let Some((_, best_index)) = best {
+        let (crossing, error) = if let Some((score, best_index)) = best {
             *prev = best_index;
+            let error = score as f64 / compare_len as f64;
+            (self.crossing_position(best_index), Some(error))
         } else {
             *prev = index;
+            (index as f64, None)
+        };
+
+        (crossing + (self.buffer_size / 2) as f64, error)
+    }
+    /// Scores the crossings from the latest back, each stopping as soon as it can no longer beat
+    /// the best so far. Ties go to the latest crossing.
+    fn best_crossing(
+        &self,
+        reference: &[f32],
+        compare_len: usize,
+        crossings: &mut dyn Iterator<Item = usize>,
+    ) -> Option<(f32, usize)> {
+        let mut best: Option<(f32, usize)> = None;
+        for i in crossings {
+            let limit = best.map_or(f32::INFINITY, |(score, _)| score);
+            let window = self.span(i - compare_len, i);
+            match abs_diff_sum(reference, &window, limit) {
+                Some(score) if score < limit => best = Some((score, i)),
+                _ => {}
+            }
+        }
+        best
+    }
+    /// Scores every crossing in full with the same f32 sums as `best_crossing`, so both agree
+    /// exactly, even on near-ties. Ties go to the latest crossing, which comes first.
+    #[cfg(any(test, feature = "bench"))]
+    fn best_crossing_unpruned(
+        &self,
+        reference: &[f32],
+        compare_len: usize,
+        crossings: &mut dyn Iterator<Item = usize>,
+    ) -> Option<(f32, usize)> {
+        crossings
+            .filter_map(|i| {
+                let window = self.span(i - compare_len, i);
+                abs_diff_sum(reference, &window, f32::INFINITY).map(|score| (score, i))
+            })
+            .min_by(|a, b| a.0.total_cmp(&b.0))
+    }
+    /// Where the signal crosses zero between `i - 1` and `i`, interpolated linearly.
+    fn crossing_position(&self, i: usize) -> f64 {
+        let (before, after) = (self.at(i - 1) as f64, self.at(i) as f64);
+        let t = before / (before - after);
+        (i - 1) as f64 + t
+    }
+    fn clamp_end(&self, end: f64) -> f64 {
+        end.clamp((self.buffer_size * 2) as f64, self.len() as f64)
+    }
+    pub fn push_live(&mut self, samples: &[Frame]) {
+        let keep = self.buffer_size * 4;
+        let live = self.live_samples(keep);
+        let total = live.len() + samples.len();
+        let drained = total.saturating_sub(keep);
+        let skipped = drained.saturating_sub(live.len());
+        live.drain(..drained.min(live.len()));
+        live.extend(
+            samples[skipped..]
+                .iter()
+                .map(|[left, right]| (left + right) / 2.0),
+        );
+        if drained > 0 {
+            self.prev_index = self
+                .prev_index
+                .saturating_sub(drained)
+                .max(self.buffer_size);
         }
 
-        *prev + self.buffer_size / 2
+        let index = self.signal_end().saturating_sub(self.buffer_size / 2 + 1);
+        let mut prev = self.prev_index;
+        let (end, _) = self.find_by_comp(
+            Self::best_crossing,
+            DEFAULT_SEARCH_WINDOW,
+            self.buffer_size,
+            index,
+            &mut prev,
+        );
+        self.prev_index = prev;
+        self.frame_indices.clear();
+        self.frame_indices.push(end);
+    }
+    /// The live window, holding at most `keep` samples so it is never reallocated.
+    fn live_samples(&mut self, keep: usize) -> &mut Vec<f32> {
+        if let Samples::Shared(shared) = &self.data {
+            let mut live = Vec::with_capacity(keep);
+            live.extend_from_slice(&shared[shared.len().saturating_sub(keep)..]);
+            self.data = Samples::Live(live);
+        }
+        match &mut self.data {
+            Samples::Live(live) => live,
+            Samples::Shared(_) => unreachable!(),
+        }
+    }
+    pub fn get_data(&self, frame: usize) -> Cow<'_, [f32]> {
+        let frame = frame.min(self.frame_indices.len().saturating_sub(1));
+        let end = self.frame_indices.get(frame).copied();
+        self.window(end.unwrap_or(self.silent_end()))
+    }
+    pub fn get_data_at(&self, time: f64) -> Cow<'_, [f32]> {
+        let frame = (self.target_fps * time) as usize;
+        self.get_data(frame)
+    }
+    pub fn trigger_at(&self, time: f64) -> f64 {
+        let Some(last) = self.frame_indices.len().checked_sub(1) else {
+            return self.silent_end();
+        };
+        let time = match self.sample_rate {
+            Some(sample_rate) => match self.song_time(time, sample_rate) {
+                Some(time) => time,
+                None => return self.len() as f64,
+            },
+            None => time.max(0.0),
+        };
+        let frame = ((self.target_fps * time) as usize).min(last);
+        let anchor = self.frame_indices[frame];
+        let Some(sample_rate) = self.sample_rate else {
+            return anchor;
+        };
+        let index = 2 * self.buffer_size + (sample_rate * time) as usize;
+        if self.frame_rate != FrameRate::Display || index >= self.len() {
+            return anchor;
+        }
+
+        // The anchor's crossing is the reference shape, so the trace stays continuous with the
+        // precomputed frames while only the crossings around `time` are searched.
+        let (search_window, compare_len) = self.trigger_window(time, sample_rate);
+        let mut prev = (anchor - (self.buffer_size / 2) as f64).ceil() as usize;
+        let (end, _) = self.find_by_comp(
+            Self::best_crossing,
+            search_window,
+            compare_len,
+            index,
+            &mut prev,
+        );
+        self.clamp_end(end)
     }
-    pub fn get_data(&self, frame: usize) -> &[f64] {
-        let frame = frame.min(self.frame_indices.len() - 1);
-        let i = self.frame_indices[frame];
-        &self.data[i - self.buffer_size..i]
+    pub fn show(&mut self, time: f64) {
+        self.shown_index = self.trigger_at(time);
+    }
+    pub fn shown_data(&self) -> Cow<'_, [f32]> {
+        self.window(self.shown_index)
+    }
+    /// Fraction of a sample between the start of `shown_data` and the interpolated trigger point.
+    pub fn shown_offset(&self) -> f32 {
+        self.shown_index.fract() as f32
+    }
+    pub fn duration(&self, sample_rate: f64) -> f64 {
+        self.data.len() as f64 / sample_rate
+    }
+    /// Maps playback time onto the song by `end_behavior`, or `None` once a stopped song is over.
+    fn song_time(&self, time: f64, sample_rate: f64) -> Option<f64> {
+        let duration = self.duration(sample_rate);
+        let time = time.max(0.0);
+        if time < duration {
+            return Some(time);
+        }
+        match self.end_behavior {
+            EndBehavior::Stop => None,
+            EndBehavior::Hold => Some(duration),
+            EndBehavior::Loop if duration > 0.0 => Some(time % duration),
+            EndBehavior::Loop => Some(0.0),
+        }
+    }
+    /// The end of the leading zero padding, which always has a full silent window before it.
+    fn silent_end(&self) -> f64 {
+        (2 * self.buffer_size) as f64
+    }
+    /// Length including the padding on both sides.
+    fn len(&self) -> usize {
+        self.data.len() + 4 * self.buffer_size
+    }
+    fn signal_end(&self) -> usize {
+        2 * self.buffer_size + self.data.len()
+    }
+    fn at(&self, i: usize) -> f32 {
+        i.checked_sub(2 * self.buffer_size)
+            .and_then(|i| self.data.get(i))
+            .copied()
+            .unwrap_or(0.0)
+    }
+    /// Samples `start..end`, borrowed unless the range reaches into the padding.
+    fn span(&self, start: usize, end: usize) -> Cow<'_, [f32]> {
+        let pad = 2 * self.buffer_size;
+        if start >= pad && end <= self.signal_end() {
+            Cow::Borrowed(&self.data[start - pad..end - pad])
+        } else {
+            Cow::Owned((start..end).map(|i| self.at(i)).collect())
+        }
+    }
+    fn window(&self, end: f64) -> Cow<'_, [f32]> {
+        let end = (end as usize).clamp(self.buffer_size, self.len());
+        self.span(end - self.buffer_size, end)
+    }
+}
+
+/// Sum of absolute differences between `a` and `b`, or `None` once it exceeds `limit`. Chunks are
+/// summed in independent lanes so the compiler can vectorize them, and the limit is checked
+/// between chunks.
+fn abs_diff_sum(a: &[f32], b: &[f32], limit: f32) -> Option<f32> {
+    const LANES: usize = 8;
+    const CHUNK: usize = 128;
+
+    let mut total = 0.0;
+    for (a, b) in a.chunks(CHUNK).zip(b.chunks(CHUNK)) {
+        let mut lanes = [0.0f32; LANES];
+        let (a_lanes, b_lanes) = (a.chunks_exact(LANES), b.chunks_exact(LANES));
+        let rest: f32 = a_lanes
+            .remainder()
+            .iter()
+            .zip(b_lanes.remainder())
+            .map(|(a, b)| (a - b).abs())
+            .sum();
+        for (a, b) in a_lanes.zip(b_lanes) {
+            for ((lane, a), b) in lanes.iter_mut().zip(a).zip(b) {
+                *lane += (a - b).abs();
+            }
+        }
+        total += lanes.iter().sum::<f32>() + rest;
+        if total > limit {
+            return None;
+        }
+    }
+    Some(total)
+}
+
+pub fn apply_gain(gain: Res<Gain>, mut query: Query<&mut ChannelData>) {
+    for mut channel in query.iter_mut() {
+        if channel.gain != gain.0 {
+            channel.gain = gain.0;
+        }
     }
 }
 
 pub fn update_channel(
     window: Query<&Window>,
-    mut query: Query<(&mut ChannelData, &mut Path)>,
+    mut query: Query<(&mut ChannelData, Option<&mut Path>, Option<&TraceMesh>)>,
+    mut meshes: ResMut<Assets<Mesh>>,
     playback: Res<PlaybackResource>,
 ) {
     let elapsed = playback.elapsed();
-    let w = window.single();
+    let Ok(w) = window.get_single() else {
+        return;
+    };
     let width = w.width() as f32;
     let height = w.height() as f32;
 
-    for (channel, mut path) in query.iter_mut() {
-        let frame = (channel.target_fps * elapsed) as usize;
-        let slice = channel.get_data(frame);
+    for (mut channel, path, trace) in query.iter_mut() {
+        channel.show(elapsed);
+        let slice = channel.shown_data();
+        let offset = channel.shown_offset();
+        let gain = channel.gain as f32;
+
+        if let Some(positions) = trace.and_then(|trace| trace.positions(&mut meshes)) {
+            samples_to_line_strip(
+                &slice,
+                offset,
+                gain,
+                channel.position,
+                width,
+                height,
+                positions,
+            );
+        } else if let Some(mut path) = path {
+            *path = samples_to_path(&slice, offset, gain, channel.position, width, height);
+        }
+    }
+}
+
+pub fn strip_rect(index: usize, strip_count: usize) -> Rect {
+    let y_spacing = 1.0 / strip_count as f32;
+    let min_y = (strip_count - 1 - index) as f32 * y_spacing;
+    Rect::new(-0.5, min_y - 0.5, 0.5, min_y + y_spacing - 0.5)
+}
+
+/// One strip per channel of `wave` plus the master, with notes and trigger settings applied.
+pub fn build_channels(
+    wave: &dyn AudioSource,
+    midi: Option<&MidiResource>,
+    trigger_mode: TriggerMode,
+    frame_rate: FrameRate,
+    end_behavior: EndBehavior,
+) -> Vec<ChannelData> {
+    let channel_count = wave.channel_count();
+    let fps = frame_rate.precompute_fps();
+
+    let mut channel_data: Vec<ChannelData> = (0..channel_count)
+        .map(|i| {
+            let rect = strip_rect(i, channel_count + 1);
+            let mut data = ChannelData::from_source(wave, i, rect, 4096, fps);
+            if let Some(notes) = midi.and_then(|m| m.tracks.get(i)) {
+                data.notes = notes.clone();
+            }
+            data.trigger_mode = trigger_mode;
+            data.frame_rate = frame_rate;
+            data.end_behavior = end_behavior;
+            data
+        })
+        .collect();
 
-        let new_path = samples_to_path(slice, channel.position, width, height);
-        *path = new_path;
+    let mut master = ChannelData::from_source(
+        wave,
+        channel_count,
+        strip_rect(channel_count, channel_count + 1),
+        4096,
+        fps,
+    );
+    if let Some(midi) = midi {
+        master.notes = midi.all_notes();
     }
+    master.trigger_mode = trigger_mode;
+    master.frame_rate = frame_rate;
+    master.end_behavior = end_behavior;
+    channel_data.push(master);
+    channel_data
 }
 
 #[derive(Component)]
 pub struct ChannelCompute(Task<CommandQueue>);
 
+#[derive(Component)]
+pub struct ChannelLabel(pub usize);
+
+#[derive(Component)]
+pub struct StripElement;
+
 pub fn setup_channels(
     mut commands: Commands,
     wave: Res<WaveResource>,
-    playback: Res<PlaybackResource>,
+    midi: Option<Res<MidiResource>>,
+    trigger_mode: Res<TriggerMode>,
+    frame_rate: Res<FrameRate>,
+    end_behavior: Res<EndBehavior>,
+    overlays: Res<Overlays>,
 ) {
+    if wave.master().is_empty() {
+        return;
+    }
     let thread_pool = AsyncComputeTaskPool::get();
 
-    let channel_count = wave.channels.len();
-    let y_spacing = 1.0 / (channel_count + 1) as f32;
-
-    let mut channel_data: Vec<ChannelData> = wave
-        .channels
-        .iter()
-        .enumerate()
-        .map(|(i, channel)| {
-            let min_y = (channel_count - i) as f32 * y_spacing;
-            let max_y = (channel_count + 1 - i) as f32 * y_spacing;
-            let rect = Rect::new(-0.5, min_y - 0.5, 0.5, max_y - 0.5);
-            ChannelData::new(channel, i, wave.channel_names[i].clone(), rect, 4096, 60.0)
-        })
-        .collect();
-
-    channel_data.push(ChannelData::new(
-        &wave.master,
-        channel_count,
-        "Master".to_string(),
-        Rect::new(-0.5, -0.5, 0.5, y_spacing - 0.5),
-        4096,
-        60.0,
-    ));
+    let mut channel_data = build_channels(
+        &**wave,
+        midi.as_deref(),
+        *trigger_mode,
+        *frame_rate,
+        *end_behavior,
+    );
 
-    let sample_rate = playback.sample_rate;
+    let sample_rate = wave.sample_rate();
 
     setup_frame(&mut commands, &channel_data);
+    setup_overlays(
+        &mut commands,
+        &channel_data,
+        sample_rate,
+        &overlays,
+        midi.is_some(),
+    );
 
-    let entity = commands
-        .spawn(NodeBundle {
-            style: Style {
-                width: Val::Percent(100.0),
-                height: Val::Percent(100.0),
-                flex_direction: FlexDirection::Column,
-                justify_content: JustifyContent::Center,
-                align_items: AlignItems::Center,
-                ..default()
-            },
-            ..default()
-        })
-        .with_children(|parent| {
-            parent.spawn(TextBundle {
-                text: Text::from_section(
-                    "Loading...",
-                    TextStyle {
-                        font_size: 40.0,
-                        color: Color::WHITE,
-                        ..default()
-                    },
-                ),
-                ..default()
-            });
+    let bars = channel_data
+        .iter()
+        .map(|data| {
+            let bar = ProgressBar {
+                done: data.progress.clone(),
+                total: data.frame_count(sample_rate),
+            };
+            (data.name.clone(), bar)
         })
-        .id();
+        .collect();
+    let entity = spawn_loading_screen(&mut commands, "Finding trigger points", bars);
+    commands.entity(entity).insert(StripElement);
 
     let task = thread_pool.spawn(async move {
         let mut command_queue = CommandQueue::default();
@@ -214,21 +721,15 @@ pub fn setup_channels(
     commands.entity(entity).insert(ChannelCompute(task));
 }
 
-fn get_bundle_for_channel(data: ChannelData) -> impl Bundle {
-    let path = PathBuilder::new().build();
-    (
-        data,
-        ShapeBundle {
-            path,
-            spatial: SpatialBundle {
-                transform: Transform::from_xyz(0.0, 0.0, 0.0),
-                ..default()
-            },
-            ..default()
-        },
-        Stroke::new(Color::hex("6cb8ff").unwrap(), 1.0),
-        Fill::color(Color::NONE),
-    )
+pub fn despawn_strips(mut commands: Commands, query: Query<Entity, With<StripElement>>) {
+    for entity in query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+/// The trace itself is attached by `TracePlugin`, depending on the `TraceRenderer`.
+pub fn get_bundle_for_channel(data: ChannelData) -> impl Bundle {
+    (data, StripElement)
 }
 
 pub fn handle_tasks(
@@ -236,54 +737,405 @@ pub fn handle_tasks(
     mut transform_tasks: Query<&mut ChannelCompute>,
     playback: ResMut<PlaybackResource>,
     data: Res<WaveResource>,
+    export: Option<Res<Export>>,
+    output: Res<PlaybackOutput>,
+    mut errors: ResMut<ErrorLog>,
 ) {
     if let Ok(mut task) = transform_tasks.get_single_mut() {
         if let Some(mut commands_queue) = block_on(future::poll_once(&mut task.0)) {
             commands.append(&mut commands_queue);
-            start_playback(playback, data)
+            if export.is_some() {
+                return start_silent(playback, data);
+            }
+            if let Some(path) = &output.recording {
+                record(
+                    data.master(),
+                    playback.sample_rate,
+                    path.clone(),
+                    &mut errors,
+                );
+            }
+            if output.audio {
+                start_playback(playback, data, &mut errors);
+            } else {
+                start_silent(playback, data);
+            }
         }
     }
 }
 
-fn setup_frame(commands: &mut Commands, channel_data: &[ChannelData]) {
+fn setup_overlays(
+    commands: &mut Commands,
+    channel_data: &[ChannelData],
+    sample_rate: f64,
+    overlays: &Overlays,
+    has_midi: bool,
+) {
+    let strip_count = channel_data.len();
+    for data in channel_data {
+        if overlays.graticule {
+            spawn_graticule(commands, data, strip_count, sample_rate);
+        }
+        if overlays.meters {
+            spawn_meter(commands, data, strip_count);
+        }
+        if overlays.stereo {
+            spawn_goniometer(commands, data, data.index + 1 == strip_count);
+        }
+        // Without any MIDI, e.g. for stems, there are no notes to map to a channel.
+        if overlays.piano_roll && has_midi {
+            spawn_note_lane(commands, data, strip_count);
+        }
+    }
+}
+
+pub fn setup_frame(commands: &mut Commands, channel_data: &[ChannelData]) {
     for data in channel_data {
         let y_spacing = 1.0 / channel_data.len() as f32;
         let name = data.name.clone();
         let min_y = data.index as f32 * y_spacing;
 
-        commands.spawn(TextBundle {
-            text: Text::from_section(
-                name,
-                TextStyle {
-                    font_size: 20.0,
-                    color: Color::WHITE,
-                    ..default()
+        commands.spawn((
+            StripElement,
+            ChannelLabel(data.index),
+            TextBundle {
+                text: Text::from_sections([
+                    TextSection {
+                        value: name,
+                        style: TextStyle {
+                            font_size: 20.0,
+                            color: Color::WHITE,
+                            ..default()
+                        },
+                    },
+                    TextSection {
+                        value: String::new(),
+                        style: TextStyle {
+                            font_size: 16.0,
+                            color: Color::WHITE,
+                            ..default()
+                        },
+                    },
+                ]),
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    top: Val::Percent(min_y * 100.0 + 1.3),
+                    left: Val::Px(10.0),
+                    ..Default::default()
                 },
-            ),
-            style: Style {
-                position_type: PositionType::Absolute,
-                top: Val::Percent(min_y * 100.0 + 1.3),
-                left: Val::Px(10.0),
-                ..Default::default()
-            },
 
-            ..default()
-        });
+                ..default()
+            },
+        ));
 
         if data.index != 0 {
             let center_y = 100.0 * (data.index as f32) * y_spacing;
-            commands.spawn(NodeBundle {
-                style: Style {
-                    position_type: PositionType::Absolute,
-                    left: Val::Percent(0.0),
-                    top: Val::Percent(center_y),
-                    width: Val::Percent(100.0),
-                    height: Val::Px(2.0),
+            commands.spawn((
+                StripElement,
+                NodeBundle {
+                    style: Style {
+                        position_type: PositionType::Absolute,
+                        left: Val::Percent(0.0),
+                        top: Val::Percent(center_y),
+                        width: Val::Percent(100.0),
+                        height: Val::Px(2.0),
+                        ..default()
+                    },
+                    background_color: BackgroundColor(Color::hex("444d56").unwrap()),
                     ..default()
                 },
-                background_color: BackgroundColor(Color::hex("444d56").unwrap()),
-                ..default()
-            });
+            ));
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_signals::{noise, saw, sine, square, Rng, SAMPLE_RATE};
+
+    const BUFFER: usize = 1024;
+
+    fn channel(samples: &[f32], buffer_size: usize) -> ChannelData {
+        let data = samples.to_vec().into();
+        let rect = Rect::new(-0.5, -0.5, 0.5, 0.5);
+        let mut channel = ChannelData::new(data, 0, "Test".into(), rect, buffer_size, 60.0);
+        channel.precompute_indices(SAMPLE_RATE);
+        channel
+    }
+
+    /// Frames whose whole window lies inside the signal, away from the zero padding.
+    fn inner_frames(channel: &ChannelData) -> Vec<usize> {
+        let signal_end = channel.signal_end();
+        (0..channel.frame_indices.len())
+            .filter(|&frame| {
+                let end = channel.frame_indices[frame];
+                end - channel.buffer_size as f64 >= (3 * channel.buffer_size) as f64
+                    && end <= signal_end as f64
+            })
+            .collect()
+    }
+
+    fn value_at(channel: &ChannelData, position: f64) -> f64 {
+        let i = position as usize;
+        let t = position.fract();
+        channel.at(i) as f64 * (1.0 - t) + channel.at(i + 1) as f64 * t
+    }
+
+    fn assert_in_bounds(channel: &ChannelData) {
+        assert!(!channel.frame_indices.is_empty());
+        for &end in &channel.frame_indices {
+            assert!(end >= (2 * channel.buffer_size) as f64);
+            assert!(end <= channel.len() as f64);
+        }
+    }
+
+    #[test]
+    fn periodic_signals_lock_to_identical_frames() {
+        let signals = [
+            ("sine", sine(441.0, 44100)),
+            ("square", square(100, 44100)),
+            ("saw", saw(100, 44100)),
+        ];
+        for (name, samples) in signals {
+            let channel = channel(&samples, BUFFER);
+            let frames = inner_frames(&channel);
+            assert!(frames.len() > 30, "{name}: only {} frames", frames.len());
+
+            for pair in frames.windows(2) {
+                let a = channel.get_data(pair[0]);
+                let b = channel.get_data(pair[1]);
+                let drift = a
+                    .iter()
+                    .zip(b.iter())
+                    .map(|(a, b)| (a - b).abs())
+                    .fold(0.0, f32::max);
+                assert!(drift < 1e-9, "{name}: frame {} drifts by {drift}", pair[1]);
+            }
+        }
+    }
+
+    #[test]
+    fn non_integer_period_locks_to_the_interpolated_crossing() {
+        let channel = channel(&sine(440.0, 44100), BUFFER);
+        let frames = inner_frames(&channel);
+        assert!(frames.len() > 30);
+
+        for &frame in &frames {
+            let crossing = channel.frame_indices[frame] - (BUFFER / 2) as f64;
+            assert!(value_at(&channel, crossing).abs() < 1e-3);
+            assert!(value_at(&channel, crossing + 1.0) > value_at(&channel, crossing));
+        }
+        for pair in frames.windows(2) {
+            let [a, b] = [pair[0], pair[1]].map(|f| channel.frame_indices[f] - BUFFER as f64);
+            for k in (0..BUFFER - 1).step_by(37) {
+                let k = k as f64;
+                assert!((value_at(&channel, a + k) - value_at(&channel, b + k)).abs() < 1e-3);
+            }
+        }
+    }
+
+    #[test]
+    fn noise_keeps_every_frame_in_bounds() {
+        let mut rng = Rng::new(3);
+        // Buffers below the search window used to index before the start of the data.
+        for buffer_size in [1, 2, 64, 400, 1024, 4096] {
+            let len = rng.below(30000);
+            let channel = channel(&noise(&mut rng, len), buffer_size);
+
+            assert_in_bounds(&channel);
+            assert_eq!(
+                channel.frame_indices.len(),
+                channel.frame_count(SAMPLE_RATE)
+            );
+        }
+    }
+
+    #[test]
+    fn pruned_search_picks_the_same_crossings_as_the_full_search() {
+        let mut rng = Rng::new(5);
+        let signals = [
+            ("sine", sine(440.0, 30000)),
+            ("square", square(100, 30000)),
+            ("saw", saw(97, 30000)),
+            ("noise", noise(&mut rng, 30000)),
+        ];
+        for (name, samples) in signals {
+            for buffer_size in [64, BUFFER] {
+                let channel = channel(&samples, buffer_size);
+                assert_eq!(
+                    channel.trigger_frames(SAMPLE_RATE, |_| {}),
+                    channel.trigger_frames_unpruned(SAMPLE_RATE, |_| {}),
+                    "{name} with a buffer of {buffer_size}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn silence_falls_back_without_triggering() {
+        let channel = channel(&[0.0; 20000], BUFFER);
+        let mut fallbacks = 0;
+        let frames = channel.trigger_frames(SAMPLE_RATE, |error| {
+            fallbacks += error.is_none() as usize;
+        });
+
+        assert_eq!(fallbacks, frames.len() - 1);
+        assert_in_bounds(&channel);
+        for frame in 0..channel.frame_indices.len() {
+            assert!(channel.get_data(frame).iter().all(|&s| s == 0.0));
+        }
+    }
+
+    #[test]
+    fn very_short_data_still_gives_full_windows() {
+        for len in [1, 2, 10, 99] {
+            let channel = channel(&sine(441.0, len), BUFFER);
+            assert_in_bounds(&channel);
+            assert_eq!(channel.get_data(0).len(), BUFFER);
+            assert_eq!(channel.get_data_at(1000.0).len(), BUFFER);
+        }
+    }
+
+    #[test]
+    fn buffer_larger_than_data() {
+        let channel = channel(&sine(441.0, 500), 4096);
+        assert_in_bounds(&channel);
+        for frame in 0..channel.frame_indices.len() {
+            assert_eq!(channel.get_data(frame).len(), 4096);
+        }
+    }
+
+    #[test]
+    fn padding_is_logical_and_signal_windows_are_borrowed() {
+        let samples = sine(441.0, 10000);
+        let channel = channel(&samples, BUFFER);
+        let pad = 2 * BUFFER;
+
+        assert_eq!(channel.data.len(), samples.len());
+        assert!(matches!(channel.span(pad, pad + BUFFER), Cow::Borrowed(_)));
+        let edge = channel.span(pad - 2, pad + 2);
+        assert_eq!(*edge, [0.0, 0.0, samples[0], samples[1]]);
+        assert!(is_silent(&channel.window(channel.len() as f64)));
+    }
+
+    fn is_silent(data: &[f32]) -> bool {
+        data.iter().all(|&s| s == 0.0)
+    }
+
+    #[test]
+    fn channel_without_frames_shows_silence() {
+        let data = vec![0.5; 100].into();
+        let rect = Rect::new(-0.5, -0.5, 0.5, 0.5);
+        let mut channel = ChannelData::new(data, 0, "Test".into(), rect, BUFFER, 60.0);
+
+        assert_eq!(channel.get_data(3).len(), BUFFER);
+        assert!(is_silent(&channel.get_data(3)));
+        channel.show(2.0);
+        assert!(is_silent(&channel.shown_data()));
+    }
+
+    #[test]
+    fn zero_length_channel_is_silent_in_every_mode() {
+        for end_behavior in [EndBehavior::Stop, EndBehavior::Loop, EndBehavior::Hold] {
+            let mut channel = channel(&[], BUFFER);
+            channel.end_behavior = end_behavior;
+            for time in [-1.0, 0.0, 0.5, 100.0] {
+                channel.show(time);
+                assert_eq!(channel.shown_data().len(), BUFFER);
+                assert!(is_silent(&channel.shown_data()));
+            }
+        }
+    }
+
+    #[test]
+    fn stop_falls_silent_after_the_end() {
+        let mut channel = channel(&sine(441.0, 44100), BUFFER);
+        channel.show(0.5);
+        assert!(!is_silent(&channel.shown_data()));
+        channel.show(1.0);
+        assert!(is_silent(&channel.shown_data()));
+        channel.show(1e9);
+        assert!(is_silent(&channel.shown_data()));
+    }
+
+    #[test]
+    fn hold_keeps_the_last_frame() {
+        let mut channel = channel(&sine(441.0, 44100), BUFFER);
+        channel.end_behavior = EndBehavior::Hold;
+        let last = channel.trigger_at(1.0);
+
+        assert!(!is_silent(&channel.window(last)));
+        assert_eq!(channel.trigger_at(3.0), last);
+        assert_eq!(channel.trigger_at(1e9), last);
+    }
+
+    #[test]
+    fn loop_wraps_to_the_start() {
+        let mut channel = channel(&sine(441.0, 44100), BUFFER);
+        channel.end_behavior = EndBehavior::Loop;
+
+        assert_eq!(channel.trigger_at(1.25), channel.trigger_at(0.25));
+        assert_eq!(channel.trigger_at(3.5), channel.trigger_at(0.5));
+    }
+
+    #[test]
+    fn negative_time_shows_the_start() {
+        let channel = channel(&sine(441.0, 44100), BUFFER);
+        assert_eq!(channel.trigger_at(-2.0), channel.trigger_at(0.0));
+    }
+
+    #[test]
+    fn song_shorter_than_the_buffer_holds_its_signal() {
+        let mut channel = channel(&sine(441.0, 300), BUFFER);
+        channel.end_behavior = EndBehavior::Hold;
+        channel.show(10.0);
+        assert!(!is_silent(&channel.shown_data()));
+    }
+
+    #[test]
+    fn live_input_refills_one_window_in_place() {
+        let rect = Rect::new(-0.5, -0.5, 0.5, 0.5);
+        let mut channel =
+            ChannelData::new(Default::default(), 0, "Live".into(), rect, BUFFER, 60.0);
+        let signal: Vec<Frame> = sine(441.0, 20 * BUFFER).iter().map(|&s| [s; 2]).collect();
+
+        channel.push_live(&signal[..BUFFER]);
+        let window = channel.data.as_ptr();
+        for chunk in signal[BUFFER..].chunks(700) {
+            channel.push_live(chunk);
+            assert!(std::ptr::eq(channel.data.as_ptr(), window));
+        }
+        assert_eq!(channel.data.len(), 4 * BUFFER);
+        let expected: Vec<f32> = signal[16 * BUFFER..].iter().map(|s| s[0]).collect();
+        assert_eq!(*channel.data, expected);
+        assert!(!is_silent(&channel.get_data(0)));
+    }
+
+    #[test]
+    fn drum_notes_leave_the_default_search_window() {
+        let note = |key, is_drum| Note {
+            key,
+            start: 0.0,
+            end: 1.0,
+            is_drum,
+        };
+        let mut drums = channel(&sine(441.0, 4096), BUFFER);
+        drums.trigger_mode = TriggerMode::Midi;
+        drums.notes = vec![note(36, true)];
+        assert_eq!(
+            drums.trigger_window(0.5, SAMPLE_RATE),
+            (DEFAULT_SEARCH_WINDOW, BUFFER)
+        );
+
+        // A kick under a pitched note must not pull the window down to the kick's key.
+        let mut master = channel(&sine(441.0, 4096), BUFFER);
+        master.trigger_mode = TriggerMode::Midi;
+        master.notes = vec![note(36, true), note(69, false)];
+        let period = SAMPLE_RATE / 440.0;
+        assert_eq!(
+            master.trigger_window(0.5, SAMPLE_RATE).0,
+            (2.0 * period) as usize
+        );
+    }
+}
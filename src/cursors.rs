@@ -0,0 +1,218 @@
+use bevy::prelude::*;
+use bevy_prototype_lyon::prelude::*;
+
+use crate::{channel::ChannelData, line::trace_sample, wave::PlaybackResource};
+
+const GRAB_DISTANCE: f32 = 8.0;
+
+pub struct CursorsPlugin;
+
+impl Plugin for CursorsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<MeasurementCursors>()
+            .add_systems(Startup, setup_cursors)
+            .add_systems(Update, toggle_cursors)
+            .add_systems(Update, drag_cursors)
+            .add_systems(
+                Update,
+                (update_cursor_lines, update_readout).after(drag_cursors),
+            );
+    }
+}
+
+#[derive(Resource)]
+pub struct MeasurementCursors {
+    pub positions: [f32; 2],
+    dragging: Option<usize>,
+    visible: bool,
+}
+
+impl Default for MeasurementCursors {
+    fn default() -> Self {
+        Self {
+            positions: [-0.25, 0.25],
+            dragging: None,
+            visible: false,
+        }
+    }
+}
+
+#[derive(Component)]
+struct CursorLine(usize);
+
+#[derive(Component)]
+struct CursorReadout;
+
+#[derive(Component)]
+struct CursorElement;
+
+fn setup_cursors(mut commands: Commands) {
+    for i in 0..2 {
+        commands.spawn((
+            CursorElement,
+            CursorLine(i),
+            ShapeBundle {
+                path: PathBuilder::new().build(),
+                spatial: SpatialBundle {
+                    transform: Transform::from_xyz(0.0, 0.0, 1.0),
+                    visibility: Visibility::Hidden,
+                    ..default()
+                },
+                ..default()
+            },
+            Stroke::new(Color::hex("e5c07b").unwrap(), 1.0),
+            Fill::color(Color::NONE),
+        ));
+    }
+
+    commands.spawn((
+        CursorElement,
+        CursorReadout,
+        TextBundle {
+            text: Text::from_section(
+                "",
+                TextStyle {
+                    font_size: 16.0,
+                    color: Color::hex("e5c07b").unwrap(),
+                    ..default()
+                },
+            ),
+            style: Style {
+                position_type: PositionType::Absolute,
+                top: Val::Percent(1.0),
+                left: Val::Percent(30.0),
+                padding: UiRect::all(Val::Px(4.0)),
+                ..default()
+            },
+            background_color: BackgroundColor(Color::BLACK.with_a(0.5)),
+            z_index: ZIndex::Global(i32::MAX),
+            visibility: Visibility::Hidden,
+            ..default()
+        },
+    ));
+}
+
+fn toggle_cursors(
+    mut cursors: ResMut<MeasurementCursors>,
+    mut q: Query<&mut Visibility, With<CursorElement>>,
+    kbd: Res<ButtonInput<KeyCode>>,
+) {
+    if kbd.just_pressed(KeyCode::KeyC) {
+        cursors.visible = !cursors.visible;
+        cursors.dragging = None;
+        for mut vis in q.iter_mut() {
+            *vis = if cursors.visible {
+                Visibility::Visible
+            } else {
+                Visibility::Hidden
+            };
+        }
+    }
+}
+
+fn drag_cursors(
+    window: Query<&Window>,
+    mut cursors: ResMut<MeasurementCursors>,
+    mouse: Res<ButtonInput<MouseButton>>,
+) {
+    if !cursors.visible {
+        return;
+    }
+    let Ok(w) = window.get_single() else {
+        return;
+    };
+    let Some(pointer) = w.cursor_position() else {
+        return;
+    };
+    let x = (pointer.x / w.width() - 0.5).clamp(-0.5, 0.5);
+
+    if mouse.just_pressed(MouseButton::Left) {
+        cursors.dragging = cursors
+            .positions
+            .iter()
+            .map(|p| (p - x).abs() * w.width())
+            .enumerate()
+            .filter(|(_, distance)| *distance <= GRAB_DISTANCE)
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(i, _)| i);
+    }
+    if !mouse.pressed(MouseButton::Left) {
+        cursors.dragging = None;
+    }
+    if let Some(i) = cursors.dragging {
+        cursors.positions[i] = x;
+    }
+}
+
+fn update_cursor_lines(
+    window: Query<&Window>,
+    cursors: Res<MeasurementCursors>,
+    mut query: Query<(&CursorLine, &mut Path)>,
+) {
+    let Ok(w) = window.get_single() else {
+        return;
+    };
+    let width = w.width();
+    let height = w.height();
+
+    for (line, mut path) in query.iter_mut() {
+        let x = cursors.positions[line.0] * width;
+        let mut path_builder = PathBuilder::new();
+        path_builder.move_to(Vec2::new(x, -height / 2.0));
+        path_builder.line_to(Vec2::new(x, height / 2.0));
+        *path = path_builder.build();
+    }
+}
+
+fn update_readout(
+    window: Query<&Window>,
+    cursors: Res<MeasurementCursors>,
+    channels: Query<&ChannelData>,
+    playback: Res<PlaybackResource>,
+    mut readout: Query<&mut Text, With<CursorReadout>>,
+    mut hovered: Local<Option<usize>>,
+) {
+    if !cursors.visible {
+        return;
+    }
+    let Ok(w) = window.get_single() else {
+        return;
+    };
+    if let Some(pointer) = w.cursor_position() {
+        let y = 0.5 - pointer.y / w.height();
+        if let Some(channel) = channels
+            .iter()
+            .find(|c| c.position.min.y <= y && y <= c.position.max.y)
+        {
+            *hovered = Some(channel.index);
+        }
+    }
+
+    let [a, b] = cursors.positions;
+    let mut text = String::new();
+    if let Some(channel) = channels.iter().find(|c| Some(c.index) == *hovered) {
+        let window_secs = channel.buffer_size as f64 / playback.sample_rate;
+        let dt = (b - a).abs() as f64 * window_secs;
+        text += &format!("dt: {:.2} ms", dt * 1000.0);
+        if dt > 0.0 {
+            text += &format!("  1/dt: {:.1} Hz", 1.0 / dt);
+        }
+
+        let slice = channel.shown_data();
+        let offset = channel.shown_offset();
+        let amplitude = |x: f32| {
+            let alpha = (x - channel.position.min.x) / channel.position.width();
+            trace_sample(&slice, offset, alpha)
+        };
+        text += &format!(
+            "  A1: {:+.3}  A2: {:+.3} ({})",
+            amplitude(a),
+            amplitude(b),
+            channel.name
+        );
+    }
+
+    for mut readout in readout.iter_mut() {
+        readout.sections[0].value = text.clone();
+    }
+}
@@ -0,0 +1,181 @@
+use std::{fmt, path::PathBuf};
+
+use bevy::prelude::*;
+
+use crate::stems::StemError;
+
+#[derive(Debug)]
+pub enum AppError {
+    MidiNotFound(PathBuf),
+    MidiUnreadable(PathBuf, std::io::Error),
+    MidiInvalid(PathBuf, midly::Error),
+    OutputNotWritable(PathBuf, String),
+    RenderUnreadable(PathBuf, String),
+    NoAudioDevice(String),
+    UnsupportedOutput(f64),
+    Audio(PathBuf, StemError),
+    LiveInput(String),
+    TrackCountMismatch { tracks: usize, channels: usize },
+    EmptyPlaylist,
+}
+
+impl fmt::Display for AppError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AppError::MidiNotFound(path) => write!(f, "MIDI file not found: {}", path.display()),
+            AppError::MidiUnreadable(path, e) => {
+                write!(f, "Could not read MIDI file {}: {e}", path.display())
+            }
+            AppError::MidiInvalid(path, e) => {
+                write!(f, "{} is not a valid MIDI file: {e}", path.display())
+            }
+            AppError::OutputNotWritable(path, e) => write!(
+                f,
+                "Output not writable, {} was not saved: {e}",
+                path.display()
+            ),
+            AppError::RenderUnreadable(path, e) => {
+                write!(f, "Could not load render {}: {e}", path.display())
+            }
+            AppError::NoAudioDevice(e) => write!(f, "No audio device: {e}"),
+            AppError::UnsupportedOutput(rate) => write!(
+                f,
+                "The audio device can't play 32-bit float samples at {rate} Hz, showing the song without sound"
+            ),
+            AppError::Audio(path, e) => {
+                write!(f, "Could not load audio from {}: {e}", path.display())
+            }
+            AppError::LiveInput(e) => write!(f, "Live input failed: {e}"),
+            AppError::TrackCountMismatch { tracks, channels } => write!(
+                f,
+                "MIDI has {tracks} note tracks but {channels} instruments were added"
+            ),
+            AppError::EmptyPlaylist => write!(f, "No song in the playlist could be loaded"),
+        }
+    }
+}
+
+impl std::error::Error for AppError {}
+
+/// What reported an error, so that it can take back its own errors once they are resolved.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum ErrorSource {
+    #[default]
+    App,
+    Reload,
+}
+
+#[derive(Resource, Default)]
+pub struct ErrorLog {
+    pub errors: Vec<(ErrorSource, AppError)>,
+}
+
+impl ErrorLog {
+    pub fn push(&mut self, error: AppError) {
+        self.push_from(ErrorSource::App, error);
+    }
+    pub fn push_from(&mut self, source: ErrorSource, error: AppError) {
+        eprintln!("{error}");
+        self.errors.push((source, error));
+    }
+    /// Removes every error `source` reported.
+    pub fn clear_from(&mut self, source: ErrorSource) {
+        self.errors.retain(|(from, _)| *from != source);
+    }
+}
+
+pub struct ErrorOverlayPlugin;
+
+impl Plugin for ErrorOverlayPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ErrorLog>()
+            .add_systems(Update, update_error_overlay)
+            .add_systems(Update, dismiss_errors);
+    }
+}
+
+#[derive(Component)]
+struct ErrorOverlay;
+
+fn update_error_overlay(
+    mut commands: Commands,
+    log: Res<ErrorLog>,
+    overlays: Query<Entity, With<ErrorOverlay>>,
+) {
+    if !log.is_changed() {
+        return;
+    }
+    for entity in overlays.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+    if log.errors.is_empty() {
+        return;
+    }
+
+    let style = |size, color| TextStyle {
+        font_size: size,
+        color,
+        ..default()
+    };
+    let mut sections: Vec<TextSection> = log
+        .errors
+        .iter()
+        .map(|(_, e)| {
+            TextSection::new(format!("{e}\n"), style(20.0, Color::hex("e06c75").unwrap()))
+        })
+        .collect();
+    sections.push(TextSection::new(
+        "Press Enter to dismiss",
+        style(14.0, Color::hex("5c6370").unwrap()),
+    ));
+
+    commands
+        .spawn((
+            ErrorOverlay,
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    width: Val::Percent(100.0),
+                    bottom: Val::Percent(5.0),
+                    justify_content: JustifyContent::Center,
+                    ..default()
+                },
+                z_index: ZIndex::Global(i32::MAX),
+                ..default()
+            },
+        ))
+        .with_children(|parent| {
+            parent.spawn(TextBundle {
+                text: Text::from_sections(sections),
+                background_color: BackgroundColor(Color::hex("21252b").unwrap().with_a(0.9)),
+                style: Style {
+                    padding: UiRect::all(Val::Px(12.0)),
+                    max_width: Val::Percent(80.0),
+                    ..default()
+                },
+                ..default()
+            });
+        });
+}
+
+fn dismiss_errors(mut log: ResMut<ErrorLog>, kbd: Res<ButtonInput<KeyCode>>) {
+    if kbd.just_pressed(KeyCode::Enter) && !log.errors.is_empty() {
+        log.errors.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clearing_a_source_keeps_identical_errors_from_others() {
+        let mut log = ErrorLog::default();
+        log.push(AppError::EmptyPlaylist);
+        log.push_from(ErrorSource::Reload, AppError::EmptyPlaylist);
+
+        log.clear_from(ErrorSource::Reload);
+        assert_eq!(log.errors.len(), 1);
+        assert_eq!(log.errors[0].0, ErrorSource::App);
+    }
+}
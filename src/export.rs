@@ -0,0 +1,127 @@
+//! Steps through the song at the fixed frame rate instead of playing it, and saves every frame as
+//! a numbered PNG, ready for e.g. `ffmpeg -framerate 60 -i frame-%06d.png`.
+
+use std::{
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use bevy::{
+    app::AppExit, prelude::*, render::view::screenshot::ScreenshotManager, window::PrimaryWindow,
+};
+
+use crate::{
+    channel::{update_channel, FrameRate},
+    error::{AppError, ErrorLog},
+    wave::PlaybackResource,
+};
+
+pub struct ExportPlugin(pub PathBuf);
+
+impl Plugin for ExportPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(Export {
+            dir: self.0.clone(),
+            fps: 60.0,
+            frame: 0,
+            saved: Arc::new(AtomicUsize::new(0)),
+            failed: Arc::new(Mutex::new(None)),
+        })
+        .add_systems(Startup, create_export_dir)
+        .add_systems(Update, show_export_frame.before(update_channel))
+        .add_systems(Last, capture_export_frame);
+    }
+}
+
+/// While this exists, playback starts silent and the time only moves on once a frame is captured.
+#[derive(Resource)]
+pub struct Export {
+    dir: PathBuf,
+    fps: f64,
+    frame: usize,
+    saved: Arc<AtomicUsize>,
+    failed: Arc<Mutex<Option<AppError>>>,
+}
+
+impl Export {
+    fn frame_path(&self) -> PathBuf {
+        self.dir.join(format!("frame-{:06}.png", self.frame))
+    }
+}
+
+fn create_export_dir(
+    mut commands: Commands,
+    mut export: ResMut<Export>,
+    frame_rate: Res<FrameRate>,
+    mut errors: ResMut<ErrorLog>,
+) {
+    export.fps = frame_rate.precompute_fps();
+    if let Err(e) = std::fs::create_dir_all(&export.dir) {
+        errors.push(AppError::OutputNotWritable(
+            export.dir.clone(),
+            e.to_string(),
+        ));
+        commands.remove_resource::<Export>();
+    }
+}
+
+fn show_export_frame(export: Option<Res<Export>>, mut playback: ResMut<PlaybackResource>) {
+    if let Some(export) = export {
+        playback.pause();
+        playback.set_time(export.frame as f64 / export.fps);
+    }
+}
+
+fn capture_export_frame(
+    mut commands: Commands,
+    export: Option<ResMut<Export>>,
+    playback: Res<PlaybackResource>,
+    window: Query<Entity, With<PrimaryWindow>>,
+    mut screenshots: ResMut<ScreenshotManager>,
+    mut errors: ResMut<ErrorLog>,
+    mut exit: EventWriter<AppExit>,
+) {
+    let (Some(mut export), Some(duration)) = (export, playback.duration()) else {
+        return;
+    };
+    if let Some(error) = export.failed.lock().unwrap().take() {
+        errors.push(error);
+        commands.remove_resource::<Export>();
+        return;
+    }
+
+    let frames = (duration * export.fps).ceil() as usize;
+    if export.frame >= frames {
+        // Frames are written in the background, so only quit once the last one is on disk.
+        if export.saved.load(Ordering::Relaxed) >= frames {
+            info!("Exported {frames} frames to {}", export.dir.display());
+            exit.send(AppExit);
+        }
+        return;
+    }
+    let Ok(window) = window.get_single() else {
+        return;
+    };
+    let path = export.frame_path();
+    let saved = export.saved.clone();
+    let failed = export.failed.clone();
+    let requested = screenshots.take_screenshot(window, move |image| {
+        let result = image
+            .try_into_dynamic()
+            .map_err(|e| e.to_string())
+            .and_then(|image| image.to_rgb8().save(&path).map_err(|e| e.to_string()));
+        match result {
+            Ok(()) => {
+                saved.fetch_add(1, Ordering::Relaxed);
+            }
+            Err(e) => *failed.lock().unwrap() = Some(AppError::OutputNotWritable(path, e)),
+        }
+    });
+    // The previous frame's capture may still be pending, in which case this frame is shown again.
+    if requested.is_ok() {
+        export.frame += 1;
+    }
+}
@@ -0,0 +1,200 @@
+use bevy::prelude::*;
+use bevy_prototype_lyon::prelude::*;
+
+use crate::{
+    channel::{ChannelData, StripElement},
+    line::lerp_rect,
+    meter::METER_WIDTH,
+};
+
+const DIVISIONS_X: usize = 10;
+const DIVISIONS_Y: usize = 4;
+const LABEL_EVERY_X: usize = 2;
+
+pub struct GraticulePlugin;
+
+impl Plugin for GraticulePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, update_graticule)
+            .add_systems(Update, update_amplitude_labels)
+            .add_systems(Update, toggle_graticule_visibility);
+    }
+}
+
+#[derive(Component)]
+pub struct Graticule {
+    rect: Rect,
+}
+
+#[derive(Component)]
+struct GraticuleElement;
+
+/// Full scale at the top (`sign` 1) or bottom (`sign` -1) of a channel's strip, as of `gain`.
+#[derive(Component)]
+struct AmplitudeLabel {
+    channel: usize,
+    sign: f64,
+    gain: f64,
+}
+
+pub fn spawn_graticule(
+    commands: &mut Commands,
+    data: &ChannelData,
+    strip_count: usize,
+    sample_rate: f64,
+) {
+    commands.spawn((
+        StripElement,
+        GraticuleElement,
+        Graticule {
+            rect: data.position,
+        },
+        ShapeBundle {
+            path: PathBuilder::new().build(),
+            spatial: SpatialBundle {
+                transform: Transform::from_xyz(0.0, 0.0, -1.0),
+                ..default()
+            },
+            ..default()
+        },
+        Stroke::new(Color::hex("353b45").unwrap(), 1.0),
+        Fill::color(Color::NONE),
+    ));
+
+    let y_spacing = 100.0 / strip_count as f32;
+    let top = data.index as f32 * y_spacing;
+    let bottom = top + y_spacing;
+
+    let window_ms = data.buffer_size as f64 / sample_rate * 1000.0;
+    for i in (LABEL_EVERY_X..DIVISIONS_X).step_by(LABEL_EVERY_X) {
+        let t = i as f32 / DIVISIONS_X as f32;
+        let ms = window_ms * t as f64;
+        spawn_label(
+            commands,
+            format!("{ms:.1} ms"),
+            Style {
+                position_type: PositionType::Absolute,
+                left: Val::Percent(t * 100.0),
+                bottom: Val::Percent(100.0 - bottom),
+                margin: UiRect::left(Val::Px(3.0)),
+                ..default()
+            },
+        );
+    }
+
+    for (sign, style) in [
+        (
+            1.0,
+            Style {
+                position_type: PositionType::Absolute,
+                right: Val::Px(METER_WIDTH + 6.0),
+                top: Val::Percent(top),
+                ..default()
+            },
+        ),
+        (
+            -1.0,
+            Style {
+                position_type: PositionType::Absolute,
+                right: Val::Px(METER_WIDTH + 6.0),
+                bottom: Val::Percent(100.0 - bottom),
+                ..default()
+            },
+        ),
+    ] {
+        let label = spawn_label(commands, amplitude_text(sign, data.gain), style);
+        commands.entity(label).insert(AmplitudeLabel {
+            channel: data.index,
+            sign,
+            gain: data.gain,
+        });
+    }
+}
+
+fn amplitude_text(sign: f64, gain: f64) -> String {
+    format!("{:+.2}", sign / gain)
+}
+
+fn spawn_label(commands: &mut Commands, text: String, style: Style) -> Entity {
+    commands
+        .spawn((
+            StripElement,
+            GraticuleElement,
+            TextBundle {
+                text: Text::from_section(
+                    text,
+                    TextStyle {
+                        font_size: 12.0,
+                        color: Color::hex("5c6370").unwrap(),
+                        ..default()
+                    },
+                ),
+                style,
+                ..default()
+            },
+        ))
+        .id()
+}
+
+fn update_amplitude_labels(
+    channels: Query<&ChannelData>,
+    mut labels: Query<(&mut AmplitudeLabel, &mut Text)>,
+) {
+    for channel in channels.iter() {
+        for (mut label, mut text) in labels.iter_mut() {
+            if label.channel == channel.index && label.gain != channel.gain {
+                label.gain = channel.gain;
+                text.sections[0].value = amplitude_text(label.sign, channel.gain);
+            }
+        }
+    }
+}
+
+fn update_graticule(
+    window: Query<&Window>,
+    mut query: Query<(&Graticule, &mut Path)>,
+    added: Query<(), Added<Graticule>>,
+    mut last_size: Local<Vec2>,
+) {
+    let Ok(w) = window.get_single() else {
+        return;
+    };
+    let size = Vec2::new(w.width(), w.height());
+    if size == *last_size && added.is_empty() {
+        return;
+    }
+    *last_size = size;
+
+    for (graticule, mut path) in query.iter_mut() {
+        *path = graticule_path(graticule.rect, size);
+    }
+}
+
+fn graticule_path(rect: Rect, size: Vec2) -> Path {
+    let mut path_builder = PathBuilder::new();
+    for i in 1..DIVISIONS_X {
+        let x = i as f32 / DIVISIONS_X as f32;
+        path_builder.move_to(lerp_rect(Vec2::new(x, 0.0), rect) * size);
+        path_builder.line_to(lerp_rect(Vec2::new(x, 1.0), rect) * size);
+    }
+    for i in 1..DIVISIONS_Y {
+        let y = i as f32 / DIVISIONS_Y as f32;
+        path_builder.move_to(lerp_rect(Vec2::new(0.0, y), rect) * size);
+        path_builder.line_to(lerp_rect(Vec2::new(1.0, y), rect) * size);
+    }
+    path_builder.build()
+}
+
+fn toggle_graticule_visibility(
+    mut q: Query<&mut Visibility, With<GraticuleElement>>,
+    kbd: Res<ButtonInput<KeyCode>>,
+) {
+    if kbd.just_pressed(KeyCode::KeyG) {
+        for mut vis in q.iter_mut() {
+            *vis = match *vis {
+                Visibility::Hidden => Visibility::Visible,
+                _ => Visibility::Hidden,
+            };
+        }
+    }
+}
@@ -0,0 +1,33 @@
+pub mod cache;
+pub mod channel;
+pub mod cursors;
+pub mod error;
+pub mod export;
+pub mod fps;
+pub mod graticule;
+pub mod line;
+pub mod live;
+pub mod loading;
+pub mod meter;
+pub mod midi;
+pub mod piano_roll;
+pub mod pitch;
+pub mod player;
+pub mod playlist;
+pub mod plugin;
+pub mod reload;
+pub mod report;
+pub mod source;
+pub mod stems;
+pub mod stereo;
+#[cfg(test)]
+mod test_signals;
+pub mod trace;
+pub mod transport;
+pub mod wave;
+
+pub use channel::ChannelData;
+pub use line::samples_to_path;
+pub use plugin::{OscilloscopePlugin, Overlays};
+pub use source::AudioSource;
+pub use wave::{WavePlugin, WaveResource};
@@ -2,33 +2,92 @@ use bevy::prelude::*;
 use bevy_prototype_lyon::{entity::Path, path::PathBuilder};
 use geo::{simplify::*, Coord, LineString};
 
-pub fn samples_to_path(samples: &[f64], rect: Rect, width: f32, height: f32) -> Path {
-    let sample_count = samples.len() as f32;
-    let points: Vec<Vec2> = samples
-        .iter()
-        .enumerate()
-        .map(|(i, &s)| {
-            Vec2::new(
-                i as f32,
-                (s as f32 * 0.5 + 0.5).clamp(0.0, 1.0) * sample_count,
-            )
-        })
-        .collect();
+/// `offset` shifts the trace left by a fraction of a sample, so a trigger between two samples
+/// lands on the same pixel every frame.
+pub fn samples_to_path(
+    samples: &[f32],
+    offset: f32,
+    gain: f32,
+    rect: Rect,
+    width: f32,
+    height: f32,
+) -> Path {
+    points_to_path(trace_points(samples, offset, gain, rect, width, height))
+}
 
-    let resampled_points: Vec<Vec2> = (0..width as usize)
-        .map(|i| {
-            let alpha = i as f32 / width;
-            let index = (alpha * sample_count) as usize;
-            points[index]
-        })
-        .collect();
+/// Fills `positions` with the trace for a line-strip mesh: one vertex per pixel column and no
+/// simplification. Leaves `positions` as they are when there is nothing to draw, so the mesh never
+/// ends up without vertices.
+pub fn samples_to_line_strip(
+    samples: &[f32],
+    offset: f32,
+    gain: f32,
+    rect: Rect,
+    width: f32,
+    height: f32,
+    positions: &mut Vec<[f32; 3]>,
+) {
+    let points = resample(samples, offset, gain, width);
+    if points.is_empty() {
+        return;
+    }
+    let points = position_points(points, samples.len() as f32, rect, width, height);
+    positions.clear();
+    positions.extend(points.into_iter().map(|p| p.extend(0.0).to_array()));
+}
+
+fn trace_points(
+    samples: &[f32],
+    offset: f32,
+    gain: f32,
+    rect: Rect,
+    width: f32,
+    height: f32,
+) -> Vec<Vec2> {
+    let resampled_points = resample(samples, offset, gain, width);
+    if resampled_points.is_empty() {
+        return Vec::new();
+    }
 
     let points = simplify_points(resampled_points, 0.5);
 
     // info!("simplified {} to {} points", samples.len(), points.len());
-    let points = position_points(points, sample_count, rect, width, height);
+    position_points(points, samples.len() as f32, rect, width, height)
+}
+
+/// One point per pixel column, in sample units.
+fn resample(samples: &[f32], offset: f32, gain: f32, width: f32) -> Vec<Vec2> {
+    if samples.is_empty() || width < 1.0 {
+        return Vec::new();
+    }
+    let sample_count = samples.len() as f32;
+
+    (0..width as usize)
+        .map(|i| {
+            let x = i as f32 / width * (sample_count - 1.0);
+            let s = sample_at(samples, x + offset);
+            Vec2::new(x, (s * gain * 0.5 + 0.5).clamp(0.0, 1.0) * sample_count)
+        })
+        .collect()
+}
 
-    points_to_path(points)
+/// The sample the trace shows at `t`, from 0 at its left edge to 1 at its right edge, with the
+/// same `offset` and interpolation the trace is drawn with.
+pub fn trace_sample(samples: &[f32], offset: f32, t: f32) -> f32 {
+    if samples.is_empty() {
+        return 0.0;
+    }
+    let x = t.clamp(0.0, 1.0) * (samples.len() - 1) as f32;
+    sample_at(samples, x + offset)
+}
+
+/// Linear interpolation between the samples around `position`, held at the last one.
+fn sample_at(samples: &[f32], position: f32) -> f32 {
+    let last = samples.len() - 1;
+    let position = position.min(last as f32);
+    let index = position as usize;
+    let next = samples[(index + 1).min(last)];
+    lerp(samples[index], next, position - index as f32)
 }
 
 fn position_points(
@@ -38,7 +97,7 @@ fn position_points(
     width: f32,
     height: f32,
 ) -> Vec<Vec2> {
-    let x_factor = 1.0 / (sample_count - 1.0);
+    let x_factor = 1.0 / (sample_count - 1.0).max(1.0);
     let y_factor = 1.0 / sample_count;
     points
         .into_iter()
@@ -52,7 +111,7 @@ fn lerp(a: f32, b: f32, t: f32) -> f32 {
     a + (b - a) * t
 }
 
-fn lerp_rect(t: Vec2, rect: Rect) -> Vec2 {
+pub fn lerp_rect(t: Vec2, rect: Rect) -> Vec2 {
     Vec2::new(
         lerp(rect.min.x, rect.max.x, t.x),
         lerp(rect.min.y, rect.max.y, t.y),
@@ -74,9 +133,144 @@ fn simplify_points(points: Vec<Vec2>, tolerance: f32) -> Vec<Vec2> {
 
 fn points_to_path(points: Vec<Vec2>) -> Path {
     let mut path_builder = PathBuilder::new();
-    path_builder.move_to(points[0]);
-    for point in points.into_iter().skip(1) {
+    let mut points = points.into_iter();
+    if let Some(first) = points.next() {
+        path_builder.move_to(first);
+    }
+    for point in points {
         path_builder.line_to(point);
     }
     path_builder.build()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_signals::{noise, Rng};
+
+    const RECT: Rect = Rect {
+        min: Vec2::new(-0.5, 0.1),
+        max: Vec2::new(0.5, 0.3),
+    };
+
+    fn assert_in_rect(points: &[Vec2], rect: Rect, width: f32, height: f32) {
+        let min = rect.min * Vec2::new(width, height);
+        let max = rect.max * Vec2::new(width, height);
+        for p in points {
+            assert!(p.is_finite(), "{p} is not finite");
+            assert!(
+                p.x >= min.x - 1e-3 && p.x <= max.x + 1e-3,
+                "{p} outside {min}..{max}"
+            );
+            assert!(
+                p.y >= min.y - 1e-3 && p.y <= max.y + 1e-3,
+                "{p} outside {min}..{max}"
+            );
+        }
+    }
+
+    #[test]
+    fn trace_sample_matches_the_drawn_trace() {
+        let samples = noise(&mut Rng::new(7), 64);
+        let (offset, gain, width) = (0.3, 1.0, 100.0);
+        for (i, point) in resample(&samples, offset, gain, width).iter().enumerate() {
+            let s = trace_sample(&samples, offset, i as f32 / width);
+            let y = (s * gain * 0.5 + 0.5) * samples.len() as f32;
+            assert!((point.y - y).abs() < 1e-4, "column {i}: {} vs {y}", point.y);
+        }
+
+        // Past the last sample the trace holds it, and so does the readout.
+        let ramp = [0.0, 1.0, 2.0, 3.0];
+        assert_eq!(trace_sample(&ramp, 0.5, 0.0), 0.5);
+        assert_eq!(trace_sample(&ramp, 0.5, 1.0), 3.0);
+        assert_eq!(trace_sample(&[], 0.5, 0.5), 0.0);
+    }
+
+    #[test]
+    fn lerp_rect_maps_unit_square_onto_rect() {
+        assert_eq!(lerp_rect(Vec2::ZERO, RECT), RECT.min);
+        assert!(lerp_rect(Vec2::ONE, RECT).abs_diff_eq(RECT.max, 1e-6));
+        assert!(lerp_rect(Vec2::splat(0.5), RECT).abs_diff_eq(RECT.center(), 1e-6));
+    }
+
+    #[test]
+    fn position_points_maps_sample_space_into_rect() {
+        let sample_count = 64.0;
+        let corners = vec![
+            Vec2::new(0.0, 0.0),
+            Vec2::new(sample_count - 1.0, 0.0),
+            Vec2::new(0.0, sample_count),
+            Vec2::new(sample_count - 1.0, sample_count),
+        ];
+        let points = position_points(corners, sample_count, RECT, 800.0, 600.0);
+
+        let size = Vec2::new(800.0, 600.0);
+        assert!(points[0].abs_diff_eq(RECT.min * size, 1e-3));
+        assert!(points[3].abs_diff_eq(RECT.max * size, 1e-3));
+        assert_in_rect(&points, RECT, 800.0, 600.0);
+    }
+
+    #[test]
+    fn trace_stays_in_rect_for_random_input() {
+        let mut rng = Rng::new(7);
+        for _ in 0..200 {
+            let len = 1 + rng.below(5000);
+            let samples = noise(&mut rng, len);
+            let gain = rng.range(0.1, 10.0) as f32;
+            let offset = rng.range(0.0, 1.0) as f32;
+            let width = rng.range(1.0, 2000.0) as f32;
+
+            let points = trace_points(&samples, offset, gain, RECT, width, 600.0);
+            assert!(!points.is_empty());
+            assert!(points.len() <= width as usize);
+            assert_in_rect(&points, RECT, width, 600.0);
+        }
+    }
+
+    #[test]
+    fn silence_is_a_flat_line_through_the_center() {
+        let points = trace_points(&[0.0; 4096], 0.0, 1.0, RECT, 800.0, 600.0);
+        let center = RECT.center().y * 600.0;
+
+        assert_eq!(points.len(), 2);
+        assert!(points.iter().all(|p| (p.y - center).abs() < 1e-3));
+    }
+
+    #[test]
+    fn offset_shifts_by_a_fraction_of_a_sample() {
+        let ramp: Vec<f32> = (0..=100).map(|i| i as f32 / 100.0 - 0.5).collect();
+        let first_value = |offset| {
+            let points = trace_points(&ramp, offset, 1.0, RECT, 100.0, 1.0);
+            (points[0].y - RECT.min.y) / RECT.height() * 2.0 - 1.0
+        };
+
+        assert!((first_value(0.0) - -0.5).abs() < 1e-4);
+        assert!((first_value(0.5) - -0.495).abs() < 1e-4);
+    }
+
+    #[test]
+    fn line_strip_has_a_vertex_per_column_inside_the_rect() {
+        let mut rng = Rng::new(5);
+        let samples = noise(&mut rng, 4096);
+        let mut positions = Vec::new();
+        samples_to_line_strip(&samples, 0.3, 2.0, RECT, 800.0, 600.0, &mut positions);
+
+        assert_eq!(positions.len(), 800);
+        let points: Vec<Vec2> = positions.iter().map(|&[x, y, _]| Vec2::new(x, y)).collect();
+        assert_in_rect(&points, RECT, 800.0, 600.0);
+        assert!(points.windows(2).all(|pair| pair[0].x < pair[1].x));
+
+        // A minimized window keeps the last trace rather than an empty mesh.
+        samples_to_line_strip(&samples, 0.0, 1.0, RECT, 0.0, 600.0, &mut positions);
+        assert_eq!(positions.len(), 800);
+    }
+
+    #[test]
+    fn degenerate_input_gives_an_empty_or_finite_trace() {
+        assert!(trace_points(&[], 0.0, 1.0, RECT, 800.0, 600.0).is_empty());
+        assert!(trace_points(&[0.5; 16], 0.0, 1.0, RECT, 0.0, 600.0).is_empty());
+
+        let single = trace_points(&[0.5], 0.9, 1.0, RECT, 800.0, 600.0);
+        assert_in_rect(&single, RECT, 800.0, 600.0);
+    }
+}
@@ -0,0 +1,259 @@
+use std::{
+    fs::File,
+    io::{self, Read},
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    thread,
+    time::Duration,
+};
+
+use bevy::prelude::*;
+
+use crate::{
+    channel::{get_bundle_for_channel, setup_frame, strip_rect, update_channel, ChannelData},
+    error::{AppError, ErrorLog},
+    graticule::spawn_graticule,
+    source::{AudioSource, Frame, SampleBuffer},
+    trace::TracePlugin,
+    wave::{PlaybackResource, WaveResource},
+};
+
+const BUFFER_SIZE: usize = 4096;
+const MAX_PENDING: usize = BUFFER_SIZE * 16;
+const TARGET_BACKLOG: usize = BUFFER_SIZE;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum LiveFormat {
+    F32,
+    I16,
+}
+
+impl LiveFormat {
+    fn bytes_per_sample(self) -> usize {
+        match self {
+            LiveFormat::F32 => 4,
+            LiveFormat::I16 => 2,
+        }
+    }
+    fn decode(self, bytes: &[u8]) -> f64 {
+        match self {
+            LiveFormat::F32 => f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as f64,
+            LiveFormat::I16 => i16::from_le_bytes([bytes[0], bytes[1]]) as f64 / 32768.0,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct LiveConfig {
+    pub source: Option<PathBuf>,
+    pub format: LiveFormat,
+    pub channels: usize,
+    pub sample_rate: f64,
+}
+
+impl LiveConfig {
+    pub fn from_args(args: &[String]) -> Result<Self, String> {
+        let mut config = Self {
+            source: None,
+            format: LiveFormat::F32,
+            channels: 2,
+            sample_rate: 44100.0,
+        };
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().ok_or(format!("missing value for {arg}"));
+            match arg.as_str() {
+                "--format" => {
+                    config.format = match value()?.as_str() {
+                        "f32" => LiveFormat::F32,
+                        "i16" => LiveFormat::I16,
+                        other => return Err(format!("unknown sample format {other}")),
+                    }
+                }
+                "--channels" => {
+                    config.channels = value()?
+                        .parse()
+                        .ok()
+                        .filter(|&c| c > 0)
+                        .ok_or("channel count must be a positive integer")?
+                }
+                "--rate" => {
+                    config.sample_rate = value()?
+                        .parse()
+                        .ok()
+                        .filter(|&r| r > 0.0)
+                        .ok_or("sample rate must be a positive number")?
+                }
+                "-" => config.source = None,
+                path => config.source = Some(PathBuf::from(path)),
+            }
+        }
+        Ok(config)
+    }
+}
+
+pub struct LiveSource {
+    config: LiveConfig,
+    pending: Arc<Mutex<Vec<Vec<f64>>>>,
+    failed: Arc<Mutex<Option<AppError>>>,
+}
+
+impl LiveSource {
+    pub fn spawn(config: LiveConfig) -> Self {
+        let pending = Arc::new(Mutex::new(vec![Vec::new(); config.channels]));
+        let failed = Arc::new(Mutex::new(None));
+        spawn_reader(config.clone(), pending.clone(), failed.clone());
+        Self {
+            config,
+            pending,
+            failed,
+        }
+    }
+}
+
+/// Why the reader thread stopped, until it is shown.
+#[derive(Resource)]
+struct LiveFailure(Arc<Mutex<Option<AppError>>>);
+
+impl AudioSource for LiveSource {
+    fn sample_rate(&self) -> f64 {
+        self.config.sample_rate
+    }
+    fn channel_count(&self) -> usize {
+        self.config.channels
+    }
+    fn channel_name(&self, index: usize) -> String {
+        format!("Input {}", index + 1)
+    }
+    fn samples(&self, _index: usize) -> Option<&SampleBuffer<Frame>> {
+        None
+    }
+    fn available(&self) -> usize {
+        let pending = self.pending.lock().unwrap();
+        pending.iter().map(|c| c.len()).min().unwrap_or(0)
+    }
+    fn read(&self, frames: usize) -> Vec<Vec<Frame>> {
+        let mut pending = self.pending.lock().unwrap();
+        let frames = frames.min(pending.iter().map(|c| c.len()).min().unwrap_or(0));
+        let mut channels: Vec<Vec<Frame>> = pending
+            .iter_mut()
+            .map(|c| c.drain(..frames).map(|s| [s as f32; 2]).collect())
+            .collect();
+        let master = (0..frames)
+            .map(|i| {
+                let sum: f32 = channels.iter().map(|c| c[i][0]).sum();
+                [sum / channels.len() as f32; 2]
+            })
+            .collect();
+        channels.push(master);
+        channels
+    }
+}
+
+pub struct LivePlugin(pub LiveConfig);
+
+impl Plugin for LivePlugin {
+    fn build(&self, app: &mut App) {
+        let source = LiveSource::spawn(self.0.clone());
+        app.init_resource::<ErrorLog>()
+            .insert_resource(LiveFailure(source.failed.clone()))
+            .insert_resource(PlaybackResource::new(self.0.sample_rate))
+            .insert_resource(WaveResource::new(source))
+            .add_systems(Startup, setup_live_channels)
+            .add_systems(Update, (read_live_input, update_channel).chain())
+            .add_systems(Update, report_live_failure);
+        if !app.is_plugin_added::<TracePlugin>() {
+            app.add_plugins(TracePlugin);
+        }
+    }
+}
+
+fn spawn_reader(
+    config: LiveConfig,
+    pending: Arc<Mutex<Vec<Vec<f64>>>>,
+    failed: Arc<Mutex<Option<AppError>>>,
+) {
+    thread::spawn(move || {
+        let fail = |e: String| *failed.lock().unwrap() = Some(AppError::LiveInput(e));
+        let mut reader: Box<dyn Read + Send> = match &config.source {
+            Some(path) => match File::open(path) {
+                Ok(file) => Box::new(file),
+                Err(e) => return fail(format!("could not open {}: {e}", path.display())),
+            },
+            None => Box::new(io::stdin()),
+        };
+
+        let sample_bytes = config.format.bytes_per_sample();
+        let frame_bytes = sample_bytes * config.channels;
+        let mut buffer = vec![0u8; frame_bytes * 512];
+        let mut bytes = Vec::new();
+
+        loop {
+            let n = match reader.read(&mut buffer) {
+                Ok(0) => break,
+                Ok(n) => n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => {
+                    fail(e.to_string());
+                    break;
+                }
+            };
+            bytes.extend_from_slice(&buffer[..n]);
+            let complete = bytes.len() / frame_bytes * frame_bytes;
+
+            // Block the producer while the display is behind, so piped files play in real time.
+            while pending.lock().unwrap()[0].len() >= MAX_PENDING {
+                thread::sleep(Duration::from_millis(5));
+            }
+
+            let mut pending = pending.lock().unwrap();
+            for frame in bytes[..complete].chunks_exact(frame_bytes) {
+                for (channel, sample) in frame.chunks_exact(sample_bytes).enumerate() {
+                    pending[channel].push(config.format.decode(sample));
+                }
+            }
+            drop(pending);
+            bytes.drain(..complete);
+        }
+    });
+}
+
+fn report_live_failure(failure: Res<LiveFailure>, mut errors: ResMut<ErrorLog>) {
+    if let Some(e) = failure.0.lock().unwrap().take() {
+        errors.push(e);
+    }
+}
+
+fn setup_live_channels(mut commands: Commands, wave: Res<WaveResource>) {
+    let channel_count = wave.channel_count();
+    let strip_count = if channel_count > 1 {
+        channel_count + 1
+    } else {
+        1
+    };
+    let channel_data: Vec<ChannelData> = (0..strip_count)
+        .map(|i| {
+            let rect = strip_rect(i, strip_count);
+            ChannelData::from_source(&**wave, i, rect, BUFFER_SIZE, 60.0)
+        })
+        .collect();
+
+    setup_frame(&mut commands, &channel_data);
+    for data in &channel_data {
+        spawn_graticule(&mut commands, data, strip_count, wave.sample_rate());
+    }
+    commands.spawn_batch(channel_data.into_iter().map(get_bundle_for_channel));
+}
+
+fn read_live_input(time: Res<Time>, wave: Res<WaveResource>, mut query: Query<&mut ChannelData>) {
+    let available = wave.available();
+    let wanted = (time.delta_seconds_f64() * wave.sample_rate()) as usize;
+    let excess = available.saturating_sub(TARGET_BACKLOG);
+    let taken = wave.read(wanted + excess / 4);
+
+    for mut channel in query.iter_mut() {
+        if let Some(samples) = taken.get(channel.index) {
+            channel.push_live(samples);
+        }
+    }
+}
@@ -0,0 +1,202 @@
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Instant,
+};
+
+use bevy::prelude::*;
+
+pub struct LoadingPlugin;
+
+impl Plugin for LoadingPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, (update_progress_bars, update_loading_status));
+    }
+}
+
+/// Filled from worker threads. A bar without a total sweeps instead, for work that reports no progress.
+#[derive(Component, Clone)]
+pub struct ProgressBar {
+    pub done: Arc<AtomicUsize>,
+    pub total: usize,
+}
+
+impl ProgressBar {
+    pub fn indeterminate() -> Self {
+        Self {
+            done: Arc::new(AtomicUsize::new(0)),
+            total: 0,
+        }
+    }
+    pub fn fraction(&self) -> Option<f32> {
+        if self.total == 0 {
+            return None;
+        }
+        let done = self.done.load(Ordering::Relaxed) as f32;
+        Some((done / self.total as f32).min(1.0))
+    }
+}
+
+#[derive(Component)]
+pub struct LoadingStatus {
+    label: String,
+    start: Instant,
+}
+
+pub fn spawn_loading_screen(
+    commands: &mut Commands,
+    label: &str,
+    bars: Vec<(String, ProgressBar)>,
+) -> Entity {
+    let text = |value: String, size: f32, color: &str| {
+        TextBundle::from_section(
+            value,
+            TextStyle {
+                font_size: size,
+                color: Color::hex(color).unwrap(),
+                ..default()
+            },
+        )
+    };
+
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                width: Val::Percent(100.0),
+                height: Val::Percent(100.0),
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..default()
+            },
+            z_index: ZIndex::Global(i32::MAX - 1),
+            ..default()
+        })
+        .with_children(|parent| {
+            parent
+                .spawn(NodeBundle {
+                    style: Style {
+                        flex_direction: FlexDirection::Column,
+                        row_gap: Val::Px(6.0),
+                        padding: UiRect::all(Val::Px(16.0)),
+                        ..default()
+                    },
+                    background_color: BackgroundColor(Color::hex("21252b").unwrap().with_a(0.9)),
+                    ..default()
+                })
+                .with_children(|panel| {
+                    panel.spawn((
+                        LoadingStatus {
+                            label: label.to_string(),
+                            start: Instant::now(),
+                        },
+                        text(format!("{label}..."), 24.0, "ffffff"),
+                    ));
+                    for (name, bar) in bars {
+                        spawn_progress_row(panel, name, bar);
+                    }
+                });
+        })
+        .id()
+}
+
+fn spawn_progress_row(parent: &mut ChildBuilder, name: String, bar: ProgressBar) {
+    parent
+        .spawn(NodeBundle {
+            style: Style {
+                align_items: AlignItems::Center,
+                column_gap: Val::Px(12.0),
+                ..default()
+            },
+            ..default()
+        })
+        .with_children(|row| {
+            row.spawn(TextBundle {
+                text: Text::from_section(
+                    name,
+                    TextStyle {
+                        font_size: 16.0,
+                        color: Color::hex("abb2bf").unwrap(),
+                        ..default()
+                    },
+                ),
+                style: Style {
+                    width: Val::Px(140.0),
+                    ..default()
+                },
+                ..default()
+            });
+            row.spawn(NodeBundle {
+                style: Style {
+                    width: Val::Px(360.0),
+                    height: Val::Px(10.0),
+                    ..default()
+                },
+                background_color: BackgroundColor(Color::hex("3e4451").unwrap()),
+                ..default()
+            })
+            .with_children(|track| {
+                track.spawn((
+                    bar,
+                    NodeBundle {
+                        style: Style {
+                            position_type: PositionType::Absolute,
+                            width: Val::Percent(0.0),
+                            height: Val::Percent(100.0),
+                            ..default()
+                        },
+                        background_color: BackgroundColor(Color::hex("6cb8ff").unwrap()),
+                        ..default()
+                    },
+                ));
+            });
+        });
+}
+
+fn update_progress_bars(time: Res<Time>, mut bars: Query<(&ProgressBar, &mut Style)>) {
+    let sweep = 1.0 - (time.elapsed_seconds() % 2.0 - 1.0).abs();
+    for (bar, mut style) in bars.iter_mut() {
+        match bar.fraction() {
+            Some(fraction) => {
+                style.left = Val::Percent(0.0);
+                style.width = Val::Percent(fraction * 100.0);
+            }
+            None => {
+                style.left = Val::Percent(sweep * 75.0);
+                style.width = Val::Percent(25.0);
+            }
+        }
+    }
+}
+
+fn update_loading_status(
+    bars: Query<&ProgressBar>,
+    mut status: Query<(&LoadingStatus, &mut Text)>,
+) {
+    let (done, total) =
+        bars.iter()
+            .filter(|bar| bar.total > 0)
+            .fold((0, 0), |(done, total), bar| {
+                let bar_done = bar.done.load(Ordering::Relaxed).min(bar.total);
+                (done + bar_done, total + bar.total)
+            });
+
+    for (status, mut text) in status.iter_mut() {
+        let elapsed = status.start.elapsed().as_secs_f64();
+        text.sections[0].value = if total == 0 {
+            format!("{}... {elapsed:.0}s", status.label)
+        } else if done == 0 {
+            format!("{}... 0%", status.label)
+        } else {
+            let fraction = done as f64 / total as f64;
+            let eta = elapsed * (1.0 - fraction) / fraction;
+            format!(
+                "{}... {:.0}%, about {eta:.0}s left",
+                status.label,
+                fraction * 100.0
+            )
+        };
+    }
+}
@@ -1,60 +1,267 @@
+use std::path::{Path, PathBuf};
+
+use oscilloscope::{
+    channel::{EndBehavior, FrameRate, Gain, TriggerMode},
+    error::AppError,
+    live::LiveConfig,
+    midi::{GmFamily, MidiResource},
+    player::output_sample_rate,
+    playlist::PlaylistEntry,
+    reload::Project,
+    source::{Arrangement, InstrumentKind},
+    trace::TraceRenderer,
+};
 use soundmaker::prelude::*;
 
 mod app;
-mod fps;
-mod line;
-mod wave;
-mod channel;
+
+const FALLBACK_SAMPLE_RATE: f64 = 44100.0;
+
+// Bump an instrument's version after changing it in soundmaker, so cached renders are redone.
+const PIANO: InstrumentKind = InstrumentKind {
+    name: "piano",
+    version: 1,
+};
+const VIOLIN: InstrumentKind = InstrumentKind {
+    name: "violin",
+    version: 1,
+};
+const FLUTE: InstrumentKind = InstrumentKind {
+    name: "flute",
+    version: 1,
+};
+const PERCUSSION: InstrumentKind = InstrumentKind {
+    name: "percussion",
+    version: 1,
+};
 
 fn main() {
-    let sample_rate = find_sample_rate();
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+    let mut options = app::ScopeOptions::default();
+    if let Some(value) = take_flag(&mut args, "--trigger") {
+        match TriggerMode::parse(&value) {
+            Ok(trigger_mode) => options.trigger_mode = trigger_mode,
+            Err(e) => return eprintln!("Invalid --trigger: {e}"),
+        }
+    }
+    if let Some(value) = take_flag(&mut args, "--fps") {
+        match FrameRate::parse(&value) {
+            Ok(rate) => options.frame_rate = rate,
+            Err(e) => return eprintln!("Invalid --fps: {e}"),
+        }
+    }
+    if let Some(value) = take_flag(&mut args, "--end") {
+        match EndBehavior::parse(&value) {
+            Ok(end_behavior) => options.end_behavior = end_behavior,
+            Err(e) => return eprintln!("Invalid --end: {e}"),
+        }
+    }
+    if let Some(value) = take_flag(&mut args, "--gain") {
+        match Gain::parse(&value) {
+            Ok(gain) => options.gain = gain,
+            Err(e) => return eprintln!("Invalid --gain: {e}"),
+        }
+    }
+    if let Some(value) = take_flag(&mut args, "--trace") {
+        match TraceRenderer::parse(&value) {
+            Ok(trace_renderer) => options.trace_renderer = trace_renderer,
+            Err(e) => return eprintln!("Invalid --trace: {e}"),
+        }
+    }
+    if let Some(dir) = take_flag(&mut args, "--export") {
+        options.export = Some(PathBuf::from(dir));
+    }
+    if let Some(path) = take_flag(&mut args, "--record") {
+        options.recording = Some(PathBuf::from(path));
+    }
+    let report = args.first().is_some_and(|arg| arg == "--report");
+    if report {
+        args.remove(0);
+    }
+    // The live window always triggers at the display rate; fixed rates are for offline output.
+    match (options.frame_rate, &options.export) {
+        (FrameRate::Fixed(_), None) if !report => {
+            return eprintln!("--fps only applies to --export and --report");
+        }
+        (FrameRate::Display, Some(_)) => options.frame_rate = FrameRate::Fixed(60),
+        _ => {}
+    }
+    if let Some((flag, rest)) = args.split_first() {
+        if flag == "--live" {
+            if options.export.is_some() {
+                return eprintln!("--export needs a song, not live input");
+            }
+            match LiveConfig::from_args(rest) {
+                Ok(config) => app::run_live(config, options),
+                Err(e) => eprintln!("Invalid live input options: {e}"),
+            }
+            return;
+        }
+    }
+    if let [flag, dir] = args.as_slice() {
+        if flag == "--stems" {
+            app::run_stems(PathBuf::from(dir), options);
+            return;
+        }
+    }
 
-    // let mut daw = chill_beats();
-    let mut daw = castle();
-    daw.master.volume = 1.0;
+    // Without an output device, songs are rendered at a common rate and shown without sound.
+    let sample_rate = match output_sample_rate() {
+        Ok(sample_rate) => sample_rate,
+        Err(e) => {
+            options.audio_error = Some(e);
+            FALLBACK_SAMPLE_RATE
+        }
+    };
 
-    app::run(daw, sample_rate);
+    if let Some((flag, paths)) = args.split_first() {
+        if flag == "--playlist" {
+            if options.export.is_some() {
+                return eprintln!("--export takes a single song, not a playlist");
+            }
+            let entries = paths
+                .iter()
+                .filter_map(|path| {
+                    playlist_entry(path, sample_rate)
+                        .map_err(|e| eprintln!("Skipping {path}: {e}"))
+                        .ok()
+                })
+                .collect();
+            app::run_playlist(entries, sample_rate, options);
+            return;
+        }
+    }
+
+    let project = project(Path::new(
+        args.first().map_or("./assets/castle.mid", String::as_str),
+    ));
+
+    if report {
+        app::run_report(project, sample_rate, options);
+    } else {
+        app::run(project, sample_rate, options);
+    }
 }
 
-fn castle() -> DAW {
-    let bytes = std::fs::read("./assets/castle.mid").unwrap();
+/// Removes `flag` and the value after it from `args`.
+fn take_flag(args: &mut Vec<String>, flag: &str) -> Option<String> {
+    let i = args.iter().position(|arg| arg == flag)?;
+    let value = args.get(i + 1).cloned().unwrap_or_default();
+    args.drain(i..(i + 2).min(args.len()));
+    Some(value)
+}
 
-    let mut daw = DAW::new();
+/// Songs in `assets` with a hand-picked arrangement; any other MIDI file is arranged from the
+/// General MIDI programs of its tracks.
+fn project(path: &Path) -> Project {
+    let build = match path.file_name().and_then(|name| name.to_str()) {
+        Some("castle.mid") => castle,
+        Some("Chill Beats.mid") => chill_beats,
+        _ => from_midi_metadata,
+    };
+    Project {
+        path: path.to_path_buf(),
+        build,
+    }
+}
+
+fn castle(path: &Path) -> Result<(Arrangement, Vec<u8>), AppError> {
+    let (bytes, _) = MidiResource::read(path)?;
+
+    let mut arrangement = Arrangement::new();
 
     let violin = violin();
     let piano = piano();
 
-    daw.add_instrument("Piano RH".to_string(), &piano, 1.0, 0.0);
-    daw.add_instrument("Piano LH".to_string(), &piano, 1.8, 0.0);
+    arrangement.add_instrument("Piano RH".to_string(), PIANO, &piano, 1.0, 0.0);
+    arrangement.add_instrument("Piano LH".to_string(), PIANO, &piano, 1.8, 0.0);
 
-    daw.add_instrument("Violin".to_string(), &violin, 1.0, 0.0);
-    daw.add_instrument("Flute".to_string(), &violin, 1.0, 0.0);
-    daw.add_instrument("Violoncello".to_string(), &violin, 1.0, 0.0);
+    arrangement.add_instrument("Violin".to_string(), VIOLIN, &violin, 1.0, 0.0);
+    arrangement.add_instrument("Flute".to_string(), VIOLIN, &violin, 1.0, 0.0);
+    arrangement.add_instrument("Violoncello".to_string(), VIOLIN, &violin, 1.0, 0.0);
 
-    daw.set_midi_bytes(&bytes);
-    daw
+    arrangement.daw.set_midi_bytes(&bytes);
+    Ok((arrangement, bytes))
 }
 
-fn chill_beats() -> DAW {
-    let midi = std::fs::read("./assets/Chill Beats.mid").unwrap();
-    let mut daw = DAW::new();
+fn chill_beats(path: &Path) -> Result<(Arrangement, Vec<u8>), AppError> {
+    let (midi, _) = MidiResource::read(path)?;
+    let mut arrangement = Arrangement::new();
 
     let violin = violin();
     let flute = flute();
 
-    let percussion = percussion(vec![
+    let percussion = percussion(drum_kit());
+
+    arrangement.add_instrument("Flute".to_string(), FLUTE, &flute, 1.0, 0.0);
+    arrangement.add_instrument(
+        "Percussion 1".to_string(),
+        PERCUSSION,
+        percussion.as_ref(),
+        1.0,
+        0.0,
+    );
+    arrangement.add_instrument(
+        "Percussion 2".to_string(),
+        PERCUSSION,
+        percussion.as_ref(),
+        1.0,
+        0.0,
+    );
+    arrangement.add_instrument("Viola".to_string(), VIOLIN, &violin, 1.0, 0.0);
+    arrangement.add_instrument("Cello".to_string(), VIOLIN, &violin, 1.0, 0.0);
+
+    arrangement.daw.set_midi_bytes(&midi);
+    Ok((arrangement, midi))
+}
+
+fn from_midi_metadata(path: &Path) -> Result<(Arrangement, Vec<u8>), AppError> {
+    let (bytes, midi) = MidiResource::read(path)?;
+    let mut arrangement = Arrangement::new();
+
+    for (i, info) in midi.track_infos.iter().enumerate() {
+        let name = info.display_name(i);
+        match info.family() {
+            GmFamily::Drums | GmFamily::Percussive => arrangement.add_instrument(
+                name,
+                PERCUSSION,
+                percussion(drum_kit()).as_ref(),
+                1.0,
+                0.0,
+            ),
+            GmFamily::Strings | GmFamily::Ensemble | GmFamily::Brass | GmFamily::SynthPad => {
+                arrangement.add_instrument(name, VIOLIN, &violin(), 1.0, 0.0)
+            }
+            GmFamily::Reed | GmFamily::Pipe | GmFamily::SynthLead => {
+                arrangement.add_instrument(name, FLUTE, &flute(), 1.0, 0.0)
+            }
+            _ => arrangement.add_instrument(name, PIANO, &piano(), 1.0, 0.0),
+        }
+    }
+
+    arrangement.daw.set_midi_bytes(&bytes);
+    Ok((arrangement, bytes))
+}
+
+fn playlist_entry(path: &str, sample_rate: f64) -> Result<PlaylistEntry, AppError> {
+    if Path::new(path).is_dir() {
+        return Ok(PlaylistEntry::Stems(PathBuf::from(path)));
+    }
+    let project = project(Path::new(path));
+    let (arrangement, midi) = project.load()?;
+    Ok(PlaylistEntry::render(
+        arrangement,
+        midi,
+        &project.path,
+        sample_rate,
+    ))
+}
+
+fn drum_kit() -> Vec<Percussion> {
+    vec![
         Percussion::BassDrum(36, 0.4),
         Percussion::SnareDrum(38, 0.7),
         Percussion::HiHat(44, 1.0),
         Percussion::Shaker(70, 1.0),
-    ]);
-
-    daw.add_instrument("Flute".to_string(), &flute, 1.0, 0.0);
-    daw.add_instrument("Percussion 1".to_string(), percussion.as_ref(), 1.0, 0.0);
-    daw.add_instrument("Percussion 2".to_string(), percussion.as_ref(), 1.0, 0.0);
-    daw.add_instrument("Viola".to_string(), &violin, 1.0, 0.0);
-    daw.add_instrument("Cello".to_string(), &violin, 1.0, 0.0);
-
-    daw.set_midi_bytes(&midi);
-    daw
+    ]
 }
@@ -0,0 +1,516 @@
+use bevy::{
+    prelude::*,
+    tasks::{block_on, futures_lite::future, AsyncComputeTaskPool, Task},
+};
+
+use crate::{
+    channel::{ChannelData, StripElement},
+    source::Frame,
+    source::SampleBuffer,
+    wave::{PlaybackResource, WaveResource},
+};
+
+pub const METER_WIDTH: f32 = 14.0;
+const MIN_DB: f32 = -60.0;
+const PEAK_HOLD_SECS: f32 = 1.5;
+const PEAK_FALL_DB_PER_SEC: f32 = 20.0;
+const LOUDNESS_BLOCK_SECS: f64 = 0.4;
+const LOUDNESS_STEP_SECS: f64 = 0.1;
+/// How much of the master the loudness pass reads before releasing it again.
+const LOUDNESS_CHUNK_SECS: f64 = 10.0;
+
+pub struct MeterPlugin;
+
+impl Plugin for MeterPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            start_loudness_compute.run_if(resource_changed::<WaveResource>),
+        )
+        .add_systems(Update, handle_loudness_task)
+        .add_systems(Update, update_meters)
+        .add_systems(Update, update_loudness_text)
+        .add_systems(Update, reset_clip_lights);
+    }
+}
+
+#[derive(Component)]
+pub struct LevelMeter {
+    index: usize,
+    rms_bar: Entity,
+    peak_bar: Entity,
+    hold_line: Entity,
+    clip_light: Entity,
+    peak_hold_db: f32,
+    hold_timer: f32,
+    clipped: bool,
+}
+
+#[derive(Component)]
+struct LoudnessText;
+
+#[derive(Component)]
+struct LoudnessCompute(Task<Loudness>);
+
+#[derive(Resource)]
+pub struct Loudness {
+    block_powers: Vec<f64>,
+    pub integrated: Option<f64>,
+}
+
+impl Loudness {
+    /// Reads the whole master once, front to back, `LOUDNESS_CHUNK_SECS` at a time. Each chunk is
+    /// released once it's weighted, so a memory-mapped render only keeps about one chunk resident
+    /// for this (3.5 MB at 44.1 kHz) rather than the whole song. What it still costs is one
+    /// sequential read of the render from disk when a song starts.
+    pub fn new(master: &SampleBuffer<Frame>, sample_rate: f64) -> Self {
+        let mut weighting = KWeighting::new(sample_rate);
+        let chunk = weighting.step * (LOUDNESS_CHUNK_SECS / LOUDNESS_STEP_SECS).round() as usize;
+        for start in (0..master.len()).step_by(chunk) {
+            let end = (start + chunk).min(master.len());
+            weighting.push(&master[start..end]);
+            master.release(start..end);
+        }
+        let block_powers = weighting.block_powers();
+        let integrated = integrated_loudness(&block_powers);
+        Self {
+            block_powers,
+            integrated,
+        }
+    }
+    pub fn integrated_until(&self, time: f64) -> Option<f64> {
+        let blocks = ((time - LOUDNESS_BLOCK_SECS) / LOUDNESS_STEP_SECS).floor() + 1.0;
+        if blocks < 1.0 {
+            return None;
+        }
+        let blocks = (blocks as usize).min(self.block_powers.len());
+        integrated_loudness(&self.block_powers[..blocks])
+    }
+}
+
+pub fn spawn_meter(commands: &mut Commands, data: &ChannelData, strip_count: usize) {
+    let y_spacing = 100.0 / strip_count as f32;
+    let top = data.index as f32 * y_spacing;
+
+    let bar = |commands: &mut Commands, color: &str, height: Val| {
+        commands
+            .spawn(NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    left: Val::Px(0.0),
+                    bottom: Val::Px(0.0),
+                    width: Val::Percent(100.0),
+                    height,
+                    ..default()
+                },
+                background_color: BackgroundColor(Color::hex(color).unwrap()),
+                ..default()
+            })
+            .id()
+    };
+    let peak_bar = bar(commands, "2f6b4f", Val::Percent(0.0));
+    let rms_bar = bar(commands, "98c379", Val::Percent(0.0));
+    let hold_line = bar(commands, "e5c07b", Val::Px(2.0));
+    let clip_light = commands
+        .spawn(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                left: Val::Px(0.0),
+                top: Val::Px(0.0),
+                width: Val::Percent(100.0),
+                height: Val::Px(6.0),
+                ..default()
+            },
+            background_color: BackgroundColor(Color::hex("3e4451").unwrap()),
+            ..default()
+        })
+        .id();
+
+    commands
+        .spawn((
+            StripElement,
+            LevelMeter {
+                index: data.index,
+                rms_bar,
+                peak_bar,
+                hold_line,
+                clip_light,
+                peak_hold_db: MIN_DB,
+                hold_timer: 0.0,
+                clipped: false,
+            },
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    right: Val::Px(2.0),
+                    top: Val::Percent(top),
+                    width: Val::Px(METER_WIDTH),
+                    height: Val::Percent(y_spacing),
+                    ..default()
+                },
+                background_color: BackgroundColor(Color::hex("21252b").unwrap()),
+                ..default()
+            },
+        ))
+        .push_children(&[peak_bar, rms_bar, hold_line, clip_light]);
+
+    if data.index + 1 == strip_count {
+        commands.spawn((
+            StripElement,
+            LoudnessText,
+            TextBundle {
+                text: Text::from_section(
+                    "",
+                    TextStyle {
+                        font_size: 16.0,
+                        color: Color::WHITE,
+                        ..default()
+                    },
+                ),
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    top: Val::Percent(top + 1.3),
+                    right: Val::Px(80.0),
+                    ..default()
+                },
+                ..default()
+            },
+        ));
+    }
+}
+
+fn db_to_fraction(db: f32) -> f32 {
+    ((db - MIN_DB) / -MIN_DB).clamp(0.0, 1.0)
+}
+
+fn amplitude_to_db(amplitude: f64) -> f32 {
+    (20.0 * amplitude.max(1e-9).log10()) as f32
+}
+
+fn update_meters(
+    time: Res<Time>,
+    wave: Res<WaveResource>,
+    playback: Res<PlaybackResource>,
+    channels: Query<&ChannelData>,
+    mut meters: Query<&mut LevelMeter>,
+    mut styles: Query<&mut Style>,
+    mut colors: Query<&mut BackgroundColor>,
+) {
+    let end = (playback.elapsed() * playback.sample_rate) as usize;
+    let dt = time.delta_seconds();
+
+    for channel in channels.iter() {
+        let Some(mut meter) = meters.iter_mut().find(|m| m.index == channel.index) else {
+            continue;
+        };
+        let window = wave.stereo_window(channel.index, end, channel.buffer_size);
+
+        let peak = window
+            .iter()
+            .map(|[l, r]| l.abs().max(r.abs()) as f64)
+            .fold(0.0, f64::max);
+        let rms = if window.is_empty() {
+            0.0
+        } else {
+            let sum: f64 = window
+                .iter()
+                .map(|&[l, r]| (l as f64 * l as f64 + r as f64 * r as f64) / 2.0)
+                .sum();
+            (sum / window.len() as f64).sqrt()
+        };
+        let peak_db = amplitude_to_db(peak);
+        let rms_db = amplitude_to_db(rms);
+
+        if peak_db >= meter.peak_hold_db {
+            meter.peak_hold_db = peak_db;
+            meter.hold_timer = PEAK_HOLD_SECS;
+        } else if meter.hold_timer > 0.0 {
+            meter.hold_timer -= dt;
+        } else {
+            meter.peak_hold_db = (meter.peak_hold_db - PEAK_FALL_DB_PER_SEC * dt).max(peak_db);
+        }
+        if peak > 1.0 {
+            meter.clipped = true;
+        }
+
+        for (entity, db) in [(meter.rms_bar, rms_db), (meter.peak_bar, peak_db)] {
+            if let Ok(mut style) = styles.get_mut(entity) {
+                style.height = Val::Percent(db_to_fraction(db) * 100.0);
+            }
+        }
+        if let Ok(mut style) = styles.get_mut(meter.hold_line) {
+            style.bottom = Val::Percent(db_to_fraction(meter.peak_hold_db) * 100.0);
+        }
+        if let Ok(mut color) = colors.get_mut(meter.clip_light) {
+            *color = BackgroundColor(if meter.clipped {
+                Color::hex("e06c75").unwrap()
+            } else {
+                Color::hex("3e4451").unwrap()
+            });
+        }
+    }
+}
+
+fn reset_clip_lights(mut meters: Query<&mut LevelMeter>, kbd: Res<ButtonInput<KeyCode>>) {
+    if kbd.just_pressed(KeyCode::KeyL) {
+        for mut meter in meters.iter_mut() {
+            meter.clipped = false;
+        }
+    }
+}
+
+fn start_loudness_compute(
+    mut commands: Commands,
+    wave: Res<WaveResource>,
+    playback: Res<PlaybackResource>,
+    pending: Query<Entity, With<LoudnessCompute>>,
+) {
+    for entity in pending.iter() {
+        commands.entity(entity).despawn();
+    }
+    commands.remove_resource::<Loudness>();
+    let master = wave.master();
+    let sample_rate = playback.sample_rate;
+    let task =
+        AsyncComputeTaskPool::get().spawn(async move { Loudness::new(&master, sample_rate) });
+    commands.spawn(LoudnessCompute(task));
+}
+
+fn handle_loudness_task(mut commands: Commands, mut tasks: Query<(Entity, &mut LoudnessCompute)>) {
+    for (entity, mut task) in tasks.iter_mut() {
+        if let Some(loudness) = block_on(future::poll_once(&mut task.0)) {
+            commands.insert_resource(loudness);
+            commands.entity(entity).despawn();
+        }
+    }
+}
+
+fn update_loudness_text(
+    loudness: Option<Res<Loudness>>,
+    playback: Res<PlaybackResource>,
+    mut query: Query<&mut Text, With<LoudnessText>>,
+) {
+    let Some(loudness) = loudness else {
+        return;
+    };
+    let format_lufs = |lufs: Option<f64>| match lufs {
+        Some(lufs) => format!("{lufs:.1}"),
+        None => "-inf".to_string(),
+    };
+    for mut text in query.iter_mut() {
+        text.sections[0].value = format!(
+            "I: {} LUFS (song {})",
+            format_lufs(loudness.integrated_until(playback.elapsed())),
+            format_lufs(loudness.integrated)
+        );
+    }
+}
+
+struct Biquad {
+    b: [f64; 3],
+    a: [f64; 2],
+    x: [f64; 2],
+    y: [f64; 2],
+}
+
+impl Biquad {
+    fn new(b: [f64; 3], a: [f64; 3]) -> Self {
+        Self {
+            b: [b[0] / a[0], b[1] / a[0], b[2] / a[0]],
+            a: [a[1] / a[0], a[2] / a[0]],
+            x: [0.0; 2],
+            y: [0.0; 2],
+        }
+    }
+    /// The K-weighting pre-filter's high shelf, from the analog prototype behind BS.1770's 48 kHz
+    /// coefficients, so other sample rates get the same response.
+    fn k_shelf(sample_rate: f64) -> Self {
+        let k = (std::f64::consts::PI * 1681.974450955533 / sample_rate).tan();
+        let q = 0.7071752369554196;
+        let vh = 10f64.powf(3.999843853973347 / 20.0);
+        let vb = vh.powf(0.4996667741545416);
+        Self::new(
+            [
+                vh + vb * k / q + k * k,
+                2.0 * (k * k - vh),
+                vh - vb * k / q + k * k,
+            ],
+            [
+                1.0 + k / q + k * k,
+                2.0 * (k * k - 1.0),
+                1.0 - k / q + k * k,
+            ],
+        )
+    }
+    /// The K-weighting RLB high-pass; its numerator is fixed at 1, -2, 1 like in the standard.
+    fn k_high_pass(sample_rate: f64) -> Self {
+        let k = (std::f64::consts::PI * 38.13547087602444 / sample_rate).tan();
+        let q = 0.5003270373238773;
+        let a0 = 1.0 + k / q + k * k;
+        Self::new(
+            [a0, -2.0 * a0, a0],
+            [a0, 2.0 * (k * k - 1.0), 1.0 - k / q + k * k],
+        )
+    }
+    fn process(&mut self, x: f64) -> f64 {
+        let y = self.b[0] * x + self.b[1] * self.x[0] + self.b[2] * self.x[1]
+            - self.a[0] * self.y[0]
+            - self.a[1] * self.y[1];
+        self.x = [x, self.x[0]];
+        self.y = [y, self.y[0]];
+        y
+    }
+}
+
+// ITU-R BS.1770 K-weighting followed by 400 ms blocks with 75% overlap.
+struct KWeighting {
+    left: [Biquad; 2],
+    right: [Biquad; 2],
+    step: usize,
+    step_sums: Vec<(f64, usize)>,
+}
+
+impl KWeighting {
+    fn new(sample_rate: f64) -> Self {
+        let k_weighting = || {
+            [
+                Biquad::k_shelf(sample_rate),
+                Biquad::k_high_pass(sample_rate),
+            ]
+        };
+        Self {
+            left: k_weighting(),
+            right: k_weighting(),
+            step: ((LOUDNESS_STEP_SECS * sample_rate) as usize).max(1),
+            step_sums: Vec::new(),
+        }
+    }
+    /// Weights the next frames. Every push but the last must be a whole number of steps long.
+    fn push(&mut self, data: &[Frame]) {
+        for chunk in data.chunks(self.step) {
+            let sum = chunk
+                .iter()
+                .map(|&[l, r]| {
+                    let l = self.left.iter_mut().fold(l as f64, |x, f| f.process(x));
+                    let r = self.right.iter_mut().fold(r as f64, |x, f| f.process(x));
+                    l * l + r * r
+                })
+                .sum();
+            self.step_sums.push((sum, chunk.len()));
+        }
+    }
+    fn block_powers(self) -> Vec<f64> {
+        let steps_per_block = (LOUDNESS_BLOCK_SECS / LOUDNESS_STEP_SECS).round() as usize;
+        // The last step can be short, so every block is divided by the samples it actually holds.
+        self.step_sums
+            .windows(steps_per_block)
+            .map(|w| {
+                let (sum, len) = w
+                    .iter()
+                    .fold((0.0, 0), |(sum, len), &(s, n)| (sum + s, len + n));
+                sum / len as f64
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+fn k_weighted_block_powers(data: &[Frame], sample_rate: f64) -> Vec<f64> {
+    let mut weighting = KWeighting::new(sample_rate);
+    weighting.push(data);
+    weighting.block_powers()
+}
+
+fn block_lufs(power: f64) -> f64 {
+    -0.691 + 10.0 * power.log10()
+}
+
+pub fn integrated_loudness(block_powers: &[f64]) -> Option<f64> {
+    let gated_mean = |threshold: f64| {
+        let gated: Vec<f64> = block_powers
+            .iter()
+            .copied()
+            .filter(|&p| p > 0.0 && block_lufs(p) > threshold)
+            .collect();
+        if gated.is_empty() {
+            None
+        } else {
+            Some(gated.iter().sum::<f64>() / gated.len() as f64)
+        }
+    };
+    let relative_threshold = block_lufs(gated_mean(-70.0)?) - 10.0;
+    gated_mean(relative_threshold.max(-70.0)).map(block_lufs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_signals::{noise, sine, Rng, SAMPLE_RATE};
+
+    fn coefficients(filter: &Biquad) -> [f64; 5] {
+        [
+            filter.b[0],
+            filter.b[1],
+            filter.b[2],
+            filter.a[0],
+            filter.a[1],
+        ]
+    }
+
+    fn assert_close(actual: [f64; 5], expected: [f64; 5]) {
+        for (a, e) in actual.into_iter().zip(expected) {
+            assert!((a - e).abs() < 1e-8, "{actual:?} != {expected:?}");
+        }
+    }
+
+    #[test]
+    fn k_weighting_matches_the_standard_at_48_khz() {
+        assert_close(
+            coefficients(&Biquad::k_shelf(48000.0)),
+            [
+                1.53512485958697,
+                -2.69169618940638,
+                1.19839281085285,
+                -1.69065929318241,
+                0.73248077421585,
+            ],
+        );
+        assert_close(
+            coefficients(&Biquad::k_high_pass(48000.0)),
+            [1.0, -2.0, 1.0, -1.99004745483398, 0.99007225036621],
+        );
+    }
+
+    #[test]
+    fn short_final_step_is_not_diluted() {
+        // A steady 1 kHz tone has the same power in every block, including the last one, which
+        // here ends in a step of only half the usual length.
+        let step = (LOUDNESS_STEP_SECS * SAMPLE_RATE) as usize;
+        let data: Vec<Frame> = sine(1000.0, 8 * step + step / 2)
+            .into_iter()
+            .map(|s| [s, s])
+            .collect();
+        let powers = k_weighted_block_powers(&data, SAMPLE_RATE);
+
+        let last = powers[powers.len() - 1];
+        let previous = powers[powers.len() - 2];
+        assert!(
+            (last - previous).abs() < 1e-3 * previous,
+            "{last} vs {previous}"
+        );
+    }
+
+    #[test]
+    fn chunked_pass_matches_a_single_pass() {
+        let samples = (LOUDNESS_CHUNK_SECS * 2.5 * SAMPLE_RATE) as usize;
+        let data: Vec<Frame> = noise(&mut Rng::new(3), samples)
+            .into_iter()
+            .map(|s| [s, s * 0.5])
+            .collect();
+        let whole = k_weighted_block_powers(&data, SAMPLE_RATE);
+        let chunked = Loudness::new(&data.into(), SAMPLE_RATE);
+
+        assert_eq!(chunked.block_powers, whole);
+        assert_eq!(chunked.integrated, integrated_loudness(&whole));
+    }
+}
@@ -0,0 +1,418 @@
+use std::{collections::HashMap, io::ErrorKind, path::Path};
+
+use bevy::prelude::*;
+use midly::{MetaMessage, MidiMessage, Smf, Timing, TrackEventKind};
+
+use crate::error::AppError;
+
+const DEFAULT_MICROS_PER_BEAT: f64 = 500_000.0;
+/// General MIDI plays percussion on channel 10, counted from zero here.
+const DRUM_CHANNEL: u8 = 9;
+
+#[derive(Clone, Debug)]
+pub struct Note {
+    pub key: u8,
+    pub start: f64,
+    pub end: f64,
+    /// On the drum channel, where the key picks a drum sound rather than a pitch.
+    pub is_drum: bool,
+}
+
+impl Note {
+    pub fn frequency(&self) -> f64 {
+        440.0 * 2f64.powf((self.key as f64 - 69.0) / 12.0)
+    }
+    pub fn is_active(&self, time: f64) -> bool {
+        self.start <= time && time < self.end
+    }
+}
+
+/// The lowest pitched note sounding at `time`. Drum hits have no pitch, so they are skipped.
+pub fn lowest_active_note(notes: &[Note], time: f64) -> Option<&Note> {
+    notes
+        .iter()
+        .take_while(|n| n.start <= time)
+        .filter(|n| !n.is_drum && n.is_active(time))
+        .min_by_key(|n| n.key)
+}
+
+struct TempoChange {
+    tick: u64,
+    seconds: f64,
+    micros_per_beat: f64,
+}
+
+struct TimeSignature {
+    tick: u64,
+    bar: u64,
+    numerator: u64,
+    denominator: u64,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct BarPosition {
+    pub bar: u64,
+    pub beat: u64,
+    pub tick: u64,
+}
+
+pub struct Timeline {
+    ticks_per_beat: f64,
+    timecode_seconds_per_tick: Option<f64>,
+    changes: Vec<TempoChange>,
+    signatures: Vec<TimeSignature>,
+}
+
+impl Timeline {
+    fn new(
+        timing: Timing,
+        mut tempos: Vec<(u64, f64)>,
+        mut signatures: Vec<(u64, u8, u8)>,
+    ) -> Self {
+        let (ticks_per_beat, timecode_seconds_per_tick) = match timing {
+            Timing::Metrical(tpb) => (tpb.as_int() as f64, None),
+            Timing::Timecode(fps, subframes) => {
+                (1.0, Some(1.0 / (fps.as_f32() as f64 * subframes as f64)))
+            }
+        };
+
+        tempos.sort_by_key(|t| t.0);
+        let mut changes = vec![TempoChange {
+            tick: 0,
+            seconds: 0.0,
+            micros_per_beat: DEFAULT_MICROS_PER_BEAT,
+        }];
+        for (tick, micros_per_beat) in tempos {
+            let last = changes.last().unwrap();
+            let seconds = last.seconds
+                + (tick - last.tick) as f64 * last.micros_per_beat / 1e6 / ticks_per_beat;
+            if last.tick == tick {
+                changes.pop();
+            }
+            changes.push(TempoChange {
+                tick,
+                seconds,
+                micros_per_beat,
+            });
+        }
+
+        signatures.sort_by_key(|s| s.0);
+        let mut timeline = Self {
+            ticks_per_beat,
+            timecode_seconds_per_tick,
+            changes,
+            signatures: vec![TimeSignature {
+                tick: 0,
+                bar: 0,
+                numerator: 4,
+                denominator: 4,
+            }],
+        };
+        for (tick, numerator, denominator_pow) in signatures {
+            let last = timeline.signatures.last().unwrap();
+            let bars = ((tick - last.tick) as f64 / timeline.ticks_per_bar(last)).ceil() as u64;
+            let bar = last.bar + bars;
+            if last.tick == tick {
+                timeline.signatures.pop();
+            }
+            timeline.signatures.push(TimeSignature {
+                tick,
+                bar,
+                numerator: numerator.max(1) as u64,
+                denominator: 1 << denominator_pow.min(6),
+            });
+        }
+        timeline
+    }
+    fn ticks_per_signature_beat(&self, signature: &TimeSignature) -> f64 {
+        self.ticks_per_beat * 4.0 / signature.denominator as f64
+    }
+    fn ticks_per_bar(&self, signature: &TimeSignature) -> f64 {
+        self.ticks_per_signature_beat(signature) * signature.numerator as f64
+    }
+    pub fn seconds(&self, tick: u64) -> f64 {
+        if let Some(seconds_per_tick) = self.timecode_seconds_per_tick {
+            return tick as f64 * seconds_per_tick;
+        }
+        let i = self.changes.partition_point(|c| c.tick <= tick) - 1;
+        let change = &self.changes[i];
+        change.seconds
+            + (tick - change.tick) as f64 * change.micros_per_beat / 1e6 / self.ticks_per_beat
+    }
+    pub fn ticks(&self, seconds: f64) -> u64 {
+        let seconds = seconds.max(0.0);
+        if let Some(seconds_per_tick) = self.timecode_seconds_per_tick {
+            return (seconds / seconds_per_tick) as u64;
+        }
+        let i = self.changes.partition_point(|c| c.seconds <= seconds) - 1;
+        let change = &self.changes[i];
+        change.tick
+            + ((seconds - change.seconds) * 1e6 / change.micros_per_beat * self.ticks_per_beat)
+                as u64
+    }
+    pub fn bpm(&self, seconds: f64) -> f64 {
+        let i = self
+            .changes
+            .partition_point(|c| c.seconds <= seconds.max(0.0))
+            - 1;
+        60e6 / self.changes[i].micros_per_beat
+    }
+    pub fn position(&self, seconds: f64) -> BarPosition {
+        let tick = self.ticks(seconds);
+        let i = self.signatures.partition_point(|s| s.tick <= tick) - 1;
+        let signature = &self.signatures[i];
+
+        let ticks_per_bar = self.ticks_per_bar(signature);
+        let ticks_per_beat = self.ticks_per_signature_beat(signature);
+        let offset = (tick - signature.tick) as f64;
+        let bars = (offset / ticks_per_bar).floor();
+        let in_bar = offset - bars * ticks_per_bar;
+        let beats = (in_bar / ticks_per_beat).floor();
+
+        BarPosition {
+            bar: signature.bar + bars as u64 + 1,
+            beat: beats as u64 + 1,
+            tick: (in_bar - beats * ticks_per_beat) as u64,
+        }
+    }
+    pub fn bar_seconds(&self, bar: u64) -> f64 {
+        let bar = bar.max(1) - 1;
+        let i = self.signatures.partition_point(|s| s.bar <= bar) - 1;
+        let signature = &self.signatures[i];
+        let tick =
+            signature.tick as f64 + (bar - signature.bar) as f64 * self.ticks_per_bar(signature);
+        self.seconds(tick as u64)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum GmFamily {
+    Piano,
+    ChromaticPercussion,
+    Organ,
+    Guitar,
+    Bass,
+    Strings,
+    Ensemble,
+    Brass,
+    Reed,
+    Pipe,
+    SynthLead,
+    SynthPad,
+    SynthEffects,
+    Ethnic,
+    Percussive,
+    SoundEffects,
+    Drums,
+}
+
+impl GmFamily {
+    pub fn from_program(program: u8) -> Self {
+        const FAMILIES: [GmFamily; 16] = [
+            GmFamily::Piano,
+            GmFamily::ChromaticPercussion,
+            GmFamily::Organ,
+            GmFamily::Guitar,
+            GmFamily::Bass,
+            GmFamily::Strings,
+            GmFamily::Ensemble,
+            GmFamily::Brass,
+            GmFamily::Reed,
+            GmFamily::Pipe,
+            GmFamily::SynthLead,
+            GmFamily::SynthPad,
+            GmFamily::SynthEffects,
+            GmFamily::Ethnic,
+            GmFamily::Percussive,
+            GmFamily::SoundEffects,
+        ];
+        FAMILIES[(program as usize / 8).min(15)]
+    }
+    pub fn name(&self) -> &'static str {
+        match self {
+            GmFamily::Piano => "Piano",
+            GmFamily::ChromaticPercussion => "Chromatic Percussion",
+            GmFamily::Organ => "Organ",
+            GmFamily::Guitar => "Guitar",
+            GmFamily::Bass => "Bass",
+            GmFamily::Strings => "Strings",
+            GmFamily::Ensemble => "Ensemble",
+            GmFamily::Brass => "Brass",
+            GmFamily::Reed => "Reed",
+            GmFamily::Pipe => "Pipe",
+            GmFamily::SynthLead => "Synth Lead",
+            GmFamily::SynthPad => "Synth Pad",
+            GmFamily::SynthEffects => "Synth Effects",
+            GmFamily::Ethnic => "Ethnic",
+            GmFamily::Percussive => "Percussive",
+            GmFamily::SoundEffects => "Sound Effects",
+            GmFamily::Drums => "Drums",
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct TrackInfo {
+    pub name: Option<String>,
+    pub program: Option<u8>,
+    pub channel: Option<u8>,
+}
+
+impl TrackInfo {
+    pub fn family(&self) -> GmFamily {
+        if self.channel == Some(DRUM_CHANNEL) {
+            GmFamily::Drums
+        } else {
+            GmFamily::from_program(self.program.unwrap_or(0))
+        }
+    }
+    pub fn display_name(&self, index: usize) -> String {
+        match (&self.name, self.program) {
+            (Some(name), _) => name.clone(),
+            (None, Some(_)) => self.family().name().to_string(),
+            (None, None) => format!("Track {}", index + 1),
+        }
+    }
+}
+
+#[derive(Resource)]
+pub struct MidiResource {
+    pub tracks: Vec<Vec<Note>>,
+    pub track_infos: Vec<TrackInfo>,
+    pub timeline: Timeline,
+}
+
+impl MidiResource {
+    pub fn parse(bytes: &[u8]) -> Result<Self, midly::Error> {
+        let smf = Smf::parse(bytes)?;
+
+        let mut tempos = Vec::new();
+        let mut signatures = Vec::new();
+        for track in smf.tracks.iter() {
+            let mut tick = 0;
+            for event in track {
+                tick += event.delta.as_int() as u64;
+                match event.kind {
+                    TrackEventKind::Meta(MetaMessage::Tempo(t)) => {
+                        tempos.push((tick, t.as_int() as f64))
+                    }
+                    TrackEventKind::Meta(MetaMessage::TimeSignature(num, den, _, _)) => {
+                        signatures.push((tick, num, den))
+                    }
+                    _ => {}
+                }
+            }
+        }
+        let timeline = Timeline::new(smf.header.timing, tempos, signatures);
+
+        let (tracks, track_infos) = smf
+            .tracks
+            .iter()
+            .map(|track| {
+                let mut tick = 0;
+                let mut held: HashMap<(u8, u8), u64> = HashMap::new();
+                let mut notes = Vec::new();
+                let mut info = TrackInfo::default();
+                for event in track {
+                    tick += event.delta.as_int() as u64;
+                    let (channel, message) = match event.kind {
+                        TrackEventKind::Midi { channel, message } => (channel, message),
+                        TrackEventKind::Meta(MetaMessage::TrackName(name)) => {
+                            let name = String::from_utf8_lossy(name).trim().to_string();
+                            if info.name.is_none() && !name.is_empty() {
+                                info.name = Some(name);
+                            }
+                            continue;
+                        }
+                        _ => continue,
+                    };
+                    let (key, velocity) = match message {
+                        MidiMessage::NoteOn { key, vel } => (key.as_int(), vel.as_int()),
+                        MidiMessage::NoteOff { key, .. } => (key.as_int(), 0),
+                        MidiMessage::ProgramChange { program } => {
+                            info.program.get_or_insert(program.as_int());
+                            continue;
+                        }
+                        _ => continue,
+                    };
+                    info.channel.get_or_insert(channel.as_int());
+                    let id = (channel.as_int(), key);
+                    if let Some(start) = held.remove(&id) {
+                        notes.push(Note {
+                            key,
+                            start: timeline.seconds(start),
+                            end: timeline.seconds(tick),
+                            is_drum: channel.as_int() == DRUM_CHANNEL,
+                        });
+                    }
+                    if velocity > 0 {
+                        held.insert(id, tick);
+                    }
+                }
+                notes.sort_by(|a, b| a.start.total_cmp(&b.start));
+                (notes, info)
+            })
+            .filter(|(notes, _)| !notes.is_empty())
+            .unzip();
+
+        Ok(Self {
+            tracks,
+            track_infos,
+            timeline,
+        })
+    }
+    pub fn read(path: &Path) -> Result<(Vec<u8>, Self), AppError> {
+        let bytes = std::fs::read(path).map_err(|e| match e.kind() {
+            ErrorKind::NotFound => AppError::MidiNotFound(path.to_path_buf()),
+            _ => AppError::MidiUnreadable(path.to_path_buf(), e),
+        })?;
+        let midi = Self::parse(&bytes).map_err(|e| AppError::MidiInvalid(path.to_path_buf(), e))?;
+        Ok((bytes, midi))
+    }
+    /// Tracks are matched to channels by position, so a different count means some notes are
+    /// shown on the wrong channel or not at all.
+    pub fn check_track_count(&self, channel_count: usize) -> Result<(), AppError> {
+        if self.tracks.len() == channel_count {
+            return Ok(());
+        }
+        Err(AppError::TrackCountMismatch {
+            tracks: self.tracks.len(),
+            channels: channel_count,
+        })
+    }
+    /// Every pitched note of every track, for the master. Drums are left out.
+    pub fn all_notes(&self) -> Vec<Note> {
+        let mut notes: Vec<Note> = self
+            .tracks
+            .iter()
+            .flatten()
+            .filter(|n| !n.is_drum)
+            .cloned()
+            .collect();
+        notes.sort_by(|a, b| a.start.total_cmp(&b.start));
+        notes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn note(key: u8, start: f64, end: f64, is_drum: bool) -> Note {
+        Note {
+            key,
+            start,
+            end,
+            is_drum,
+        }
+    }
+
+    #[test]
+    fn drum_hits_never_count_as_the_lowest_note() {
+        let notes = [note(36, 0.0, 1.0, true), note(60, 0.5, 2.0, false)];
+
+        assert!(lowest_active_note(&notes, 0.25).is_none());
+        assert_eq!(lowest_active_note(&notes, 0.75).unwrap().key, 60);
+        assert_eq!(lowest_active_note(&notes, 1.5).unwrap().key, 60);
+    }
+}
@@ -0,0 +1,182 @@
+use bevy::prelude::*;
+use bevy_prototype_lyon::prelude::*;
+
+use crate::{
+    channel::{ChannelData, StripElement},
+    line::lerp_rect,
+    wave::PlaybackResource,
+};
+
+const LANE_HEIGHT: f32 = 0.18;
+const WINDOW_SECS: f64 = 2.0;
+
+pub struct PianoRollPlugin;
+
+impl Plugin for PianoRollPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, update_note_lanes)
+            .add_systems(Update, toggle_note_lanes);
+    }
+}
+
+#[derive(Component)]
+pub struct NoteLane {
+    index: usize,
+    rect: Rect,
+    min_key: u8,
+    max_key: u8,
+}
+
+#[derive(Component, PartialEq, Eq)]
+enum LanePart {
+    Idle,
+    Active,
+    Playhead,
+}
+
+#[derive(Component)]
+struct NoteLaneElement;
+
+pub fn spawn_note_lane(commands: &mut Commands, data: &ChannelData, strip_count: usize) {
+    let rect = Rect::new(
+        data.position.min.x,
+        data.position.min.y,
+        data.position.max.x,
+        data.position.min.y + data.position.height() * LANE_HEIGHT,
+    );
+    let min_key = data.notes.iter().map(|n| n.key).min().unwrap_or(60);
+    let max_key = data.notes.iter().map(|n| n.key).max().unwrap_or(60);
+    let lane = || NoteLane {
+        index: data.index,
+        rect,
+        min_key,
+        max_key,
+    };
+
+    for (part, z, color) in [
+        (LanePart::Idle, -0.6, "3e4451"),
+        (LanePart::Active, -0.5, "61afef"),
+    ] {
+        commands.spawn((
+            StripElement,
+            NoteLaneElement,
+            lane(),
+            part,
+            ShapeBundle {
+                path: PathBuilder::new().build(),
+                spatial: SpatialBundle {
+                    transform: Transform::from_xyz(0.0, 0.0, z),
+                    ..default()
+                },
+                ..default()
+            },
+            Fill::color(Color::hex(color).unwrap()),
+        ));
+    }
+    commands.spawn((
+        StripElement,
+        NoteLaneElement,
+        lane(),
+        LanePart::Playhead,
+        ShapeBundle {
+            path: PathBuilder::new().build(),
+            spatial: SpatialBundle {
+                transform: Transform::from_xyz(0.0, 0.0, -0.4),
+                ..default()
+            },
+            ..default()
+        },
+        Stroke::new(Color::hex("e06c75").unwrap(), 1.0),
+        Fill::color(Color::NONE),
+    ));
+
+    if data.notes.is_empty() {
+        let y_spacing = 100.0 / strip_count as f32;
+        let bottom = (data.index + 1) as f32 * y_spacing;
+        commands.spawn((
+            StripElement,
+            NoteLaneElement,
+            TextBundle {
+                text: Text::from_section(
+                    "No MIDI notes mapped to this channel",
+                    TextStyle {
+                        font_size: 14.0,
+                        color: Color::hex("e06c75").unwrap(),
+                        ..default()
+                    },
+                ),
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    left: Val::Percent(50.0),
+                    bottom: Val::Percent(100.0 - bottom + y_spacing * LANE_HEIGHT * 0.2),
+                    ..default()
+                },
+                ..default()
+            },
+        ));
+    }
+}
+
+fn update_note_lanes(
+    window: Query<&Window>,
+    playback: Res<PlaybackResource>,
+    channels: Query<&ChannelData>,
+    mut lanes: Query<(&NoteLane, &LanePart, &mut Path)>,
+) {
+    let Ok(w) = window.get_single() else {
+        return;
+    };
+    let size = Vec2::new(w.width(), w.height());
+    let time = playback.elapsed();
+    let start = time - WINDOW_SECS;
+    let to_x = |t: f64| ((t - start) / (2.0 * WINDOW_SECS)).clamp(0.0, 1.0) as f32;
+
+    for (lane, part, mut path) in lanes.iter_mut() {
+        let mut path_builder = PathBuilder::new();
+
+        if *part == LanePart::Playhead {
+            path_builder.move_to(lerp_rect(Vec2::new(0.5, 0.0), lane.rect) * size);
+            path_builder.line_to(lerp_rect(Vec2::new(0.5, 1.0), lane.rect) * size);
+            *path = path_builder.build();
+            continue;
+        }
+
+        let Some(channel) = channels.iter().find(|c| c.index == lane.index) else {
+            continue;
+        };
+        let key_count = (lane.max_key - lane.min_key) as f32 + 1.0;
+        let notes = channel
+            .notes
+            .iter()
+            .take_while(|n| n.start < time + WINDOW_SECS)
+            .filter(|n| n.end > start)
+            .filter(|n| n.is_active(time) == (*part == LanePart::Active));
+
+        for note in notes {
+            let y0 = (note.key - lane.min_key) as f32 / key_count;
+            let y1 = y0 + 1.0 / key_count;
+            let min = lerp_rect(Vec2::new(to_x(note.start), y0), lane.rect) * size;
+            let max = lerp_rect(Vec2::new(to_x(note.end), y1), lane.rect) * size;
+            path_builder.move_to(min);
+            path_builder.line_to(Vec2::new(max.x, min.y));
+            path_builder.line_to(max);
+            path_builder.line_to(Vec2::new(min.x, max.y));
+            path_builder.close();
+        }
+        *path = path_builder.build();
+    }
+}
+
+fn toggle_note_lanes(
+    mut q: Query<&mut Visibility, With<NoteLaneElement>>,
+    kbd: Res<ButtonInput<KeyCode>>,
+) {
+    if kbd.just_pressed(KeyCode::KeyP) {
+        for mut vis in q.iter_mut() {
+            *vis = match *vis {
+                Visibility::Hidden => Visibility::Visible,
+                _ => Visibility::Hidden,
+            };
+        }
+    }
+}
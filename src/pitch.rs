@@ -0,0 +1,207 @@
+use bevy::prelude::*;
+use rustfft::{num_complex::Complex, FftPlanner};
+
+use crate::{
+    channel::{ChannelData, ChannelLabel},
+    midi::Note,
+    wave::PlaybackResource,
+};
+
+const MIN_FREQUENCY: f64 = 30.0;
+const MAX_FREQUENCY: f64 = 4000.0;
+const CLARITY_THRESHOLD: f64 = 0.5;
+/// How far from a playing MIDI note an estimate may be and still be measured against it.
+const MAX_MIDI_CENTS: f64 = 100.0;
+const NOTE_NAMES: [&str; 12] = [
+    "C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B",
+];
+
+pub struct PitchPlugin;
+
+impl Plugin for PitchPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(PitchTimer(Timer::from_seconds(0.1, TimerMode::Repeating)))
+            .add_systems(Update, update_pitch_readout);
+    }
+}
+
+#[derive(Resource)]
+struct PitchTimer(Timer);
+
+pub fn estimate_frequency(samples: &[f32], sample_rate: f64) -> Option<f64> {
+    let n = samples.len();
+    let min_lag = (sample_rate / MAX_FREQUENCY).floor().max(1.0) as usize;
+    let max_lag = ((sample_rate / MIN_FREQUENCY).ceil() as usize).min(n / 2);
+    if min_lag + 2 >= max_lag {
+        return None;
+    }
+
+    let size = (2 * n).next_power_of_two();
+    let mut planner = FftPlanner::new();
+    let mut buffer: Vec<Complex<f64>> = samples
+        .iter()
+        .map(|&s| Complex::new(s as f64, 0.0))
+        .chain(std::iter::repeat(Complex::new(0.0, 0.0)))
+        .take(size)
+        .collect();
+    planner.plan_fft_forward(size).process(&mut buffer);
+    for x in buffer.iter_mut() {
+        *x = Complex::new(x.norm_sqr(), 0.0);
+    }
+    planner.plan_fft_inverse(size).process(&mut buffer);
+
+    let energy = buffer[0].re;
+    if energy <= f64::EPSILON {
+        return None;
+    }
+    // Normalize each lag by its overlap so long periods are not penalized.
+    let r = |k: usize| buffer[k].re / energy * n as f64 / (n - k) as f64;
+
+    let best = (min_lag..max_lag).map(r).fold(f64::MIN, f64::max);
+    if best < CLARITY_THRESHOLD {
+        return None;
+    }
+
+    // Take the first strong peak to avoid picking a multiple of the period.
+    let lag = (min_lag + 1..max_lag - 1)
+        .find(|&k| r(k) >= 0.9 * best && r(k) >= r(k - 1) && r(k) >= r(k + 1))?;
+
+    let (prev, cur, next) = (r(lag - 1), r(lag), r(lag + 1));
+    let denom = prev - 2.0 * cur + next;
+    let offset = if denom.abs() > f64::EPSILON {
+        0.5 * (prev - next) / denom
+    } else {
+        0.0
+    };
+
+    Some(sample_rate / (lag as f64 + offset))
+}
+
+fn midi_number(frequency: f64) -> f64 {
+    69.0 + 12.0 * (frequency / 440.0).log2()
+}
+
+fn key_name(key: i32) -> String {
+    format!(
+        "{}{}",
+        NOTE_NAMES[key.rem_euclid(12) as usize],
+        key.div_euclid(12) - 1
+    )
+}
+
+/// The nearest note to `frequency` and how many cents `frequency` is off from it.
+pub fn note_name(frequency: f64) -> (String, f64) {
+    let midi = midi_number(frequency);
+    let nearest = midi.round();
+    (key_name(nearest as i32), (midi - nearest) * 100.0)
+}
+
+/// Like `note_name`, but measured against the MIDI note sounding at `time` that's closest to
+/// `frequency`, so a patch detuned by more than a quarter tone still shows the note it plays.
+/// Falls back to the nearest note when no pitched note is within `MAX_MIDI_CENTS`.
+pub fn note_name_from_midi(frequency: f64, notes: &[Note], time: f64) -> (String, f64) {
+    let midi = midi_number(frequency);
+    notes
+        .iter()
+        .take_while(|n| n.start <= time)
+        .filter(|n| !n.is_drum && n.is_active(time))
+        .map(|n| (n.key, (midi - n.key as f64) * 100.0))
+        .filter(|(_, cents)| cents.abs() < MAX_MIDI_CENTS)
+        .min_by(|a, b| a.1.abs().total_cmp(&b.1.abs()))
+        .map(|(key, cents)| (key_name(key as i32), cents))
+        .unwrap_or_else(|| note_name(frequency))
+}
+
+fn update_pitch_readout(
+    time: Res<Time>,
+    mut timer: ResMut<PitchTimer>,
+    channels: Query<&ChannelData>,
+    mut labels: Query<(&ChannelLabel, &mut Text)>,
+    playback: Res<PlaybackResource>,
+) {
+    if !timer.0.tick(time.delta()).just_finished() {
+        return;
+    }
+    for channel in channels.iter() {
+        let Some((_, mut text)) = labels.iter_mut().find(|(l, _)| l.0 == channel.index) else {
+            continue;
+        };
+        let slice = channel.shown_data();
+
+        let section = &mut text.sections[1];
+        if let Some(frequency) = estimate_frequency(&slice, playback.sample_rate) {
+            let (name, cents) = note_name_from_midi(frequency, &channel.notes, playback.elapsed());
+            section.value = format!("  {frequency:.1} Hz  {name} {cents:+.0}c");
+            section.style.color = if cents.abs() <= 10.0 {
+                Color::hex("98c379").unwrap()
+            } else if cents.abs() <= 25.0 {
+                Color::hex("e5c07b").unwrap()
+            } else {
+                Color::hex("e06c75").unwrap()
+            };
+        } else {
+            section.value = String::new();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_signals::{sine, SAMPLE_RATE};
+
+    const WINDOW: usize = 4096;
+
+    fn note(key: u8, is_drum: bool) -> Note {
+        Note {
+            key,
+            start: 0.0,
+            end: 1.0,
+            is_drum,
+        }
+    }
+
+    #[test]
+    fn sine_gives_its_frequency_and_note() {
+        for (frequency, expected) in [(440.0, "A4"), (261.63, "C4"), (82.41, "E2")] {
+            let estimate = estimate_frequency(&sine(frequency, WINDOW), SAMPLE_RATE).unwrap();
+            assert!(
+                (estimate - frequency).abs() < 0.005 * frequency,
+                "{estimate} Hz for {frequency} Hz"
+            );
+            let (name, cents) = note_name(estimate);
+            assert_eq!(name, expected);
+            assert!(cents.abs() < 10.0, "{name} {cents:+.1}c");
+        }
+    }
+
+    #[test]
+    fn detuned_sine_reports_its_cents_offset() {
+        // A quarter of a semitone sharp of A4.
+        let frequency = 440.0 * 2f64.powf(25.0 / 1200.0);
+        let estimate = estimate_frequency(&sine(frequency, WINDOW), SAMPLE_RATE).unwrap();
+        let (name, cents) = note_name(estimate);
+        assert_eq!(name, "A4");
+        assert!((cents - 25.0).abs() < 5.0, "{cents:+.1}c");
+    }
+
+    #[test]
+    fn silence_gives_no_estimate() {
+        assert_eq!(estimate_frequency(&[0.0; WINDOW], SAMPLE_RATE), None);
+    }
+
+    #[test]
+    fn midi_note_is_kept_past_a_quarter_tone() {
+        // 70 cents sharp of A4 is nearer to A#4, but the MIDI says A4 is what's playing.
+        let frequency = 440.0 * 2f64.powf(70.0 / 1200.0);
+        let (name, cents) = note_name_from_midi(frequency, &[note(69, false)], 0.5);
+        assert_eq!(name, "A4");
+        assert!((cents - 70.0).abs() < 1e-6);
+
+        // Drums and notes an octave away don't count, so the nearest note is used instead.
+        let notes = [note(69, true), note(57, false)];
+        let (name, cents) = note_name_from_midi(frequency, &notes, 0.5);
+        assert_eq!(name, "A#4");
+        assert!((cents + 30.0).abs() < 1e-6);
+    }
+}
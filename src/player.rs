@@ -0,0 +1,226 @@
+//! Plays the master mix on the default output device, reading it straight from its shared buffer
+//! so playback needs no copy of the song.
+
+use std::{
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        mpsc::Sender,
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+
+use crate::{
+    error::AppError,
+    source::{Frame, SampleBuffer},
+};
+
+const NO_SEEK: f64 = f64::NAN;
+
+/// Shared between `PlaybackResource` and the audio callback.
+#[derive(Clone)]
+pub struct PlayerControls(Arc<ControlState>);
+
+struct ControlState {
+    paused: AtomicBool,
+    /// The pending seek as f64 bits, NaN if there is none, so the audio callback never blocks.
+    seek: AtomicU64,
+    volume: AtomicU64,
+    stopped: AtomicBool,
+}
+
+impl PlayerControls {
+    fn new() -> Self {
+        Self(Arc::new(ControlState {
+            paused: AtomicBool::new(false),
+            seek: AtomicU64::new(NO_SEEK.to_bits()),
+            volume: AtomicU64::new(1f64.to_bits()),
+            stopped: AtomicBool::new(false),
+        }))
+    }
+    pub fn set_paused(&self, paused: bool) {
+        self.0.paused.store(paused, Ordering::Relaxed);
+    }
+    pub fn seek(&self, time: f64) {
+        self.0.seek.store(time.to_bits(), Ordering::Relaxed);
+    }
+    pub fn volume(&self) -> f64 {
+        f64::from_bits(self.0.volume.load(Ordering::Relaxed))
+    }
+    pub fn set_volume(&self, volume: f64) {
+        self.0.volume.store(volume.to_bits(), Ordering::Relaxed);
+    }
+    /// Ends playback for good and releases the output device.
+    pub fn stop(&self) {
+        self.0.stopped.store(true, Ordering::Relaxed);
+    }
+}
+
+/// The default output device's sample rate, which songs are rendered at.
+pub fn output_sample_rate() -> Result<f64, AppError> {
+    let device = cpal::default_host()
+        .default_output_device()
+        .ok_or(AppError::NoAudioDevice("no output device".to_string()))?;
+    let config = device
+        .default_output_config()
+        .map_err(|e| AppError::NoAudioDevice(e.to_string()))?;
+    Ok(config.sample_rate().0 as f64)
+}
+
+/// Plays `master` until it is stopped, sending the start time and the controls once the output
+/// is running. Nothing is resampled or converted, so the device has to take f32 samples at the
+/// song's sample rate.
+pub fn play(
+    master: SampleBuffer<Frame>,
+    sample_rate: f64,
+    tx: Sender<(Instant, PlayerControls)>,
+) -> Result<(), AppError> {
+    let no_device = |e: String| AppError::NoAudioDevice(e);
+    let device = cpal::default_host()
+        .default_output_device()
+        .ok_or(no_device("no output device".to_string()))?;
+    let channels = device
+        .default_output_config()
+        .map_err(|e| no_device(e.to_string()))?
+        .channels();
+    let rate = cpal::SampleRate(sample_rate as u32);
+    let supported = device
+        .supported_output_configs()
+        .map_err(|e| no_device(e.to_string()))?
+        .any(|c| {
+            c.channels() == channels
+                && c.sample_format() == cpal::SampleFormat::F32
+                && c.min_sample_rate() <= rate
+                && rate <= c.max_sample_rate()
+        });
+    if !supported {
+        return Err(AppError::UnsupportedOutput(sample_rate));
+    }
+    let config = cpal::StreamConfig {
+        channels,
+        sample_rate: rate,
+        buffer_size: cpal::BufferSize::Default,
+    };
+
+    let controls = PlayerControls::new();
+    let mut cursor = Cursor::new(master, sample_rate, controls.clone());
+    let stream = device
+        .build_output_stream(
+            &config,
+            move |data: &mut [f32], _: &cpal::OutputCallbackInfo| {
+                cursor.fill(data, channels as usize)
+            },
+            |e| eprintln!("Playback error: {e}"),
+            None,
+        )
+        .map_err(|e| no_device(e.to_string()))?;
+    stream.play().map_err(|e| no_device(e.to_string()))?;
+    if tx.send((Instant::now(), controls.clone())).is_err() {
+        return Ok(());
+    }
+
+    // The stream plays on its own thread and stops when dropped, so keep it until it is stopped.
+    while !controls.0.stopped.load(Ordering::Relaxed) {
+        thread::sleep(Duration::from_millis(50));
+    }
+    Ok(())
+}
+
+/// Writes `samples` as a 32-bit float WAV file, straight from the shared buffer.
+pub fn save_wav(samples: &[Frame], sample_rate: f64, path: &Path) -> Result<(), hound::Error> {
+    let spec = hound::WavSpec {
+        channels: 2,
+        sample_rate: sample_rate as u32,
+        bits_per_sample: 32,
+        sample_format: hound::SampleFormat::Float,
+    };
+    let mut writer = hound::WavWriter::create(path, spec)?;
+    for &sample in samples.iter().flatten() {
+        writer.write_sample(sample)?;
+    }
+    writer.finalize()
+}
+
+/// Where the audio callback is in the song.
+struct Cursor {
+    master: SampleBuffer<Frame>,
+    position: usize,
+    sample_rate: f64,
+    controls: PlayerControls,
+}
+
+impl Cursor {
+    fn new(master: SampleBuffer<Frame>, sample_rate: f64, controls: PlayerControls) -> Self {
+        Self {
+            master,
+            position: 0,
+            sample_rate,
+            controls,
+        }
+    }
+    /// Fills an interleaved output buffer with `channels` samples per frame.
+    fn fill(&mut self, data: &mut [f32], channels: usize) {
+        let state = &self.controls.0;
+        let time = f64::from_bits(state.seek.swap(NO_SEEK.to_bits(), Ordering::Relaxed));
+        if !time.is_nan() {
+            self.position = (time.max(0.0) * self.sample_rate) as usize;
+        }
+        let paused = state.paused.load(Ordering::Relaxed);
+        let volume = self.controls.volume() as f32;
+
+        for out in data.chunks_mut(channels.max(1)) {
+            let [left, right] = match self.master.get(self.position) {
+                Some(&frame) if !paused => {
+                    self.position += 1;
+                    frame
+                }
+                _ => [0.0; 2],
+            };
+            match out {
+                [mono] => *mono = (left + right) / 2.0 * volume,
+                [out_left, out_right, rest @ ..] => {
+                    *out_left = left * volume;
+                    *out_right = right * volume;
+                    rest.fill(0.0);
+                }
+                [] => {}
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cursor_follows_the_controls() {
+        let master: Vec<Frame> = (0..8).map(|i| [i as f32, 2.0 * i as f32]).collect();
+        let controls = PlayerControls::new();
+        let mut cursor = Cursor::new(master.into(), 4.0, controls.clone());
+        let mut out = [0.0; 4];
+
+        cursor.fill(&mut out, 2);
+        assert_eq!(out, [0.0, 0.0, 1.0, 2.0]);
+
+        controls.set_paused(true);
+        cursor.fill(&mut out, 2);
+        assert_eq!(out, [0.0; 4]);
+        assert_eq!(cursor.position, 2);
+
+        // Seeking to one second lands on frame 4; a mono output gets the average of both sides.
+        controls.set_paused(false);
+        controls.set_volume(0.5);
+        controls.seek(1.0);
+        cursor.fill(&mut out, 1);
+        assert_eq!(out, [3.0, 3.75, 4.5, 5.25]);
+
+        // Past the end it plays silence, so looping can still seek back.
+        cursor.fill(&mut out, 1);
+        assert_eq!(out, [0.0; 4]);
+    }
+}
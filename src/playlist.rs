@@ -0,0 +1,227 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use bevy::{
+    prelude::*,
+    tasks::{block_on, futures_lite::future, AsyncComputeTaskPool, Task},
+};
+
+use crate::{
+    error::{AppError, ErrorLog},
+    loading::{spawn_loading_screen, ProgressBar},
+    midi::MidiResource,
+    source::{get_render, render_cache_path, Arrangement},
+    stems::load_stems,
+    wave::{PlaybackResource, WaveResource},
+};
+
+pub struct PlaylistPlugin;
+
+impl Plugin for PlaylistPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, advance_playlist)
+            .add_systems(Update, handle_song_load);
+    }
+}
+
+#[derive(Clone)]
+pub enum PlaylistEntry {
+    /// Rendered the first time it is loaded, unless a valid cache already exists.
+    Render {
+        source: PathBuf,
+        cache: PathBuf,
+        arrangement: Arc<Mutex<Arrangement>>,
+        midi: Vec<u8>,
+        sample_rate: f64,
+    },
+    Stems(PathBuf),
+}
+
+pub struct Song {
+    pub wave: WaveResource,
+    pub midi: Option<MidiResource>,
+    pub sample_rate: f64,
+    pub warning: Option<AppError>,
+}
+
+impl PlaylistEntry {
+    pub fn render(
+        arrangement: Arrangement,
+        midi: Vec<u8>,
+        source: &Path,
+        sample_rate: f64,
+    ) -> Self {
+        PlaylistEntry::Render {
+            source: source.to_path_buf(),
+            cache: render_cache_path(source, &midi, &arrangement.instruments, sample_rate),
+            arrangement: Arc::new(Mutex::new(arrangement)),
+            midi,
+            sample_rate,
+        }
+    }
+    pub fn load(&self) -> Result<Song, AppError> {
+        match self {
+            PlaylistEntry::Render {
+                cache,
+                arrangement,
+                midi,
+                sample_rate,
+                ..
+            } => {
+                let mut arrangement = arrangement.lock().unwrap();
+                let (wave, warning) = get_render(&mut arrangement.daw, *sample_rate, cache.clone());
+                Ok(Song {
+                    sample_rate: *sample_rate,
+                    wave,
+                    midi: MidiResource::parse(midi).ok(),
+                    warning,
+                })
+            }
+            PlaylistEntry::Stems(dir) => {
+                let source = load_stems(dir).map_err(|e| AppError::Audio(dir.clone(), e))?;
+                Ok(Song {
+                    sample_rate: source.sample_rate,
+                    wave: WaveResource::new(source),
+                    midi: None,
+                    warning: None,
+                })
+            }
+        }
+    }
+    fn path(&self) -> &PathBuf {
+        match self {
+            PlaylistEntry::Render { source, .. } => source,
+            PlaylistEntry::Stems(dir) => dir,
+        }
+    }
+    fn name(&self) -> String {
+        let path = self.path();
+        path.file_stem()
+            .unwrap_or(path.as_os_str())
+            .to_string_lossy()
+            .into_owned()
+    }
+}
+
+#[derive(Resource)]
+pub struct Playlist {
+    entries: Vec<PlaylistEntry>,
+    next: usize,
+    failures: usize,
+    /// The next song, loaded while the current one plays.
+    ready: Option<Song>,
+}
+
+impl Playlist {
+    /// Nothing is loaded up front; the first song starts loading once the app runs.
+    pub fn new(entries: Vec<PlaylistEntry>) -> Self {
+        Self {
+            entries,
+            next: 0,
+            failures: 0,
+            ready: None,
+        }
+    }
+}
+
+#[derive(Component)]
+struct SongLoad {
+    task: Task<Result<Song, AppError>>,
+    name: String,
+}
+
+/// Shown while the current song has ended but the next one is still loading.
+#[derive(Component)]
+struct SongLoadScreen;
+
+/// Starts loading the next song as soon as the current one is playing, and switches to it once
+/// the current one ends.
+fn advance_playlist(
+    mut commands: Commands,
+    mut playlist: ResMut<Playlist>,
+    wave: Res<WaveResource>,
+    playback: Res<PlaybackResource>,
+    loading: Query<&SongLoad>,
+    screens: Query<Entity, With<SongLoadScreen>>,
+    mut errors: ResMut<ErrorLog>,
+) {
+    let at_end = playback.elapsed() >= wave.duration();
+    if at_end {
+        if let Some(song) = playlist.ready.take() {
+            for entity in screens.iter() {
+                commands.entity(entity).despawn_recursive();
+            }
+            if let Some(Err(e)) = song
+                .midi
+                .as_ref()
+                .map(|midi| midi.check_track_count(song.wave.channel_count()))
+            {
+                errors.push(e);
+            }
+            commands.insert_resource(PlaybackResource::new(song.sample_rate));
+            match song.midi {
+                Some(midi) => commands.insert_resource(midi),
+                None => commands.remove_resource::<MidiResource>(),
+            }
+            commands.insert_resource(song.wave);
+            return;
+        }
+    }
+
+    if let Ok(load) = loading.get_single() {
+        // Not a real progress bar: `get_render` can't measure rendering, so there is no ETA either.
+        if at_end && screens.is_empty() {
+            let bars = vec![(load.name.clone(), ProgressBar::indeterminate())];
+            let entity = spawn_loading_screen(&mut commands, "Loading next song", bars);
+            commands.entity(entity).insert(SongLoadScreen);
+        }
+        return;
+    }
+    if playlist.ready.is_some() || playlist.failures >= playlist.entries.len() {
+        return;
+    }
+
+    let entry = playlist.entries[playlist.next].clone();
+    playlist.next = (playlist.next + 1) % playlist.entries.len();
+    info!("Loading {}", entry.path().display());
+    let name = entry.name();
+    let task = AsyncComputeTaskPool::get().spawn(async move { entry.load() });
+    commands.spawn(SongLoad { task, name });
+}
+
+fn handle_song_load(
+    mut commands: Commands,
+    mut playlist: ResMut<Playlist>,
+    mut tasks: Query<(Entity, &mut SongLoad)>,
+    screens: Query<Entity, With<SongLoadScreen>>,
+    mut errors: ResMut<ErrorLog>,
+) {
+    for (entity, mut load) in tasks.iter_mut() {
+        let Some(song) = block_on(future::poll_once(&mut load.task)) else {
+            continue;
+        };
+        commands.entity(entity).despawn();
+
+        match song {
+            Ok(mut song) => {
+                playlist.failures = 0;
+                if let Some(warning) = song.warning.take() {
+                    errors.push(warning);
+                }
+                playlist.ready = Some(song);
+            }
+            Err(e) => {
+                errors.push(e);
+                playlist.failures += 1;
+                // With nothing left to load, the screen would otherwise wait forever.
+                if playlist.failures >= playlist.entries.len() {
+                    for entity in screens.iter() {
+                        commands.entity(entity).despawn_recursive();
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,188 @@
+use std::{path::PathBuf, sync::Mutex};
+
+use bevy::prelude::*;
+use bevy_prototype_lyon::prelude::*;
+
+use crate::{
+    channel::{EndBehavior, FrameRate, Gain, TriggerMode},
+    cursors::CursorsPlugin,
+    error::ErrorOverlayPlugin,
+    export::ExportPlugin,
+    graticule::GraticulePlugin,
+    loading::LoadingPlugin,
+    meter::MeterPlugin,
+    piano_roll::PianoRollPlugin,
+    pitch::PitchPlugin,
+    source::AudioSource,
+    stereo::StereoPlugin,
+    trace::{TracePlugin, TraceRenderer},
+    transport::TransportPlugin,
+    wave::{PlaybackKeysPlugin, PlaybackOutput, WavePlugin, WaveResource},
+};
+
+#[derive(Resource, Clone, Copy, Debug)]
+pub struct Overlays {
+    pub graticule: bool,
+    pub cursors: bool,
+    pub pitch: bool,
+    pub meters: bool,
+    pub stereo: bool,
+    pub piano_roll: bool,
+    pub transport: bool,
+}
+
+impl Default for Overlays {
+    fn default() -> Self {
+        Self {
+            graticule: true,
+            cursors: true,
+            pitch: true,
+            meters: true,
+            stereo: true,
+            piano_roll: true,
+            transport: true,
+        }
+    }
+}
+
+pub struct OscilloscopePlugin {
+    wave: Mutex<Option<WaveResource>>,
+    sample_rate: f64,
+    trigger_mode: TriggerMode,
+    frame_rate: FrameRate,
+    end_behavior: EndBehavior,
+    gain: Gain,
+    trace_renderer: TraceRenderer,
+    overlays: Overlays,
+    export: Option<PathBuf>,
+    audio_output: bool,
+    playback_keys: bool,
+    recording: Option<PathBuf>,
+}
+
+impl OscilloscopePlugin {
+    pub fn new(source: impl AudioSource + 'static) -> Self {
+        let sample_rate = source.sample_rate();
+        Self::from_wave(WaveResource::new(source), sample_rate)
+    }
+    pub fn from_wave(wave: WaveResource, sample_rate: f64) -> Self {
+        Self {
+            wave: Mutex::new(Some(wave)),
+            sample_rate,
+            trigger_mode: TriggerMode::Fixed,
+            frame_rate: FrameRate::Display,
+            end_behavior: EndBehavior::Stop,
+            gain: Gain::default(),
+            trace_renderer: TraceRenderer::Mesh,
+            overlays: Overlays::default(),
+            export: None,
+            audio_output: false,
+            playback_keys: false,
+            recording: None,
+        }
+    }
+    pub fn with_trigger_mode(mut self, trigger_mode: TriggerMode) -> Self {
+        self.trigger_mode = trigger_mode;
+        self
+    }
+    pub fn with_frame_rate(mut self, frame_rate: FrameRate) -> Self {
+        self.frame_rate = frame_rate;
+        self
+    }
+    pub fn with_end_behavior(mut self, end_behavior: EndBehavior) -> Self {
+        self.end_behavior = end_behavior;
+        self
+    }
+    pub fn with_gain(mut self, gain: Gain) -> Self {
+        self.gain = gain;
+        self
+    }
+    pub fn with_trace_renderer(mut self, trace_renderer: TraceRenderer) -> Self {
+        self.trace_renderer = trace_renderer;
+        self
+    }
+    pub fn with_overlays(mut self, overlays: Overlays) -> Self {
+        self.overlays = overlays;
+        self
+    }
+    /// Saves every frame to `dir` at the fixed frame rate instead of playing the song.
+    pub fn with_export(mut self, dir: PathBuf) -> Self {
+        self.export = Some(dir);
+        self
+    }
+    /// Plays the song on the default output device. Without it, only the clock runs.
+    pub fn with_audio_output(mut self) -> Self {
+        self.audio_output = true;
+        self
+    }
+    /// Binds Space, R and the up and down arrows to pause, restart and volume.
+    pub fn with_playback_keys(mut self) -> Self {
+        self.playback_keys = true;
+        self
+    }
+    /// Also saves the master mix to `path` as a WAV file whenever playback starts.
+    pub fn with_recording(mut self, path: PathBuf) -> Self {
+        self.recording = Some(path);
+        self
+    }
+}
+
+impl Plugin for OscilloscopePlugin {
+    fn build(&self, app: &mut App) {
+        if let Some(wave) = self.wave.lock().unwrap().take() {
+            app.insert_resource(wave);
+        }
+        app.insert_resource(self.trigger_mode)
+            .insert_resource(self.frame_rate)
+            .insert_resource(self.end_behavior)
+            .insert_resource(self.gain)
+            .insert_resource(self.trace_renderer)
+            .insert_resource(self.overlays)
+            .insert_resource(PlaybackOutput {
+                audio: self.audio_output,
+                recording: self.recording.clone(),
+            })
+            .add_plugins(WavePlugin(self.sample_rate));
+        if !app.is_plugin_added::<ShapePlugin>() {
+            app.add_plugins(ShapePlugin);
+        }
+        if !app.is_plugin_added::<TracePlugin>() {
+            app.add_plugins(TracePlugin);
+        }
+        if !app.is_plugin_added::<ErrorOverlayPlugin>() {
+            app.add_plugins(ErrorOverlayPlugin);
+        }
+        if !app.is_plugin_added::<LoadingPlugin>() {
+            app.add_plugins(LoadingPlugin);
+        }
+        if self.playback_keys {
+            app.add_plugins(PlaybackKeysPlugin);
+        }
+        if let Some(dir) = &self.export {
+            app.add_plugins(ExportPlugin(dir.clone()));
+        }
+
+        let overlays = self.overlays;
+        if overlays.graticule {
+            app.add_plugins(GraticulePlugin);
+        }
+        if overlays.cursors {
+            app.add_plugins(CursorsPlugin);
+        }
+        if overlays.pitch {
+            app.add_plugins(PitchPlugin);
+        }
+        if overlays.meters {
+            app.add_plugins(MeterPlugin);
+        }
+        if overlays.stereo {
+            app.add_plugins(StereoPlugin);
+        }
+        if overlays.piano_roll {
+            app.add_plugins(PianoRollPlugin);
+        }
+        if overlays.transport {
+            app.add_plugins(TransportPlugin);
+        }
+    }
+}
@@ -0,0 +1,176 @@
+use std::{
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use bevy::{
+    prelude::*,
+    tasks::{block_on, futures_lite::future, AsyncComputeTaskPool, Task},
+};
+
+use crate::{
+    error::{AppError, ErrorLog, ErrorSource},
+    loading::{spawn_loading_screen, LoadingPlugin, ProgressBar},
+    midi::MidiResource,
+    source::{get_render, render_cache_path, Arrangement},
+    wave::{PlaybackResource, WaveResource},
+};
+
+const POLL_SECS: f32 = 0.5;
+
+pub type ProjectBuild = fn(&Path) -> Result<(Arrangement, Vec<u8>), AppError>;
+
+#[derive(Clone)]
+pub struct Project {
+    pub path: PathBuf,
+    pub build: ProjectBuild,
+}
+
+impl Project {
+    pub fn load(&self) -> Result<(Arrangement, Vec<u8>), AppError> {
+        let (mut arrangement, midi) = (self.build)(&self.path)?;
+        arrangement.daw.master.volume = 1.0;
+        Ok((arrangement, midi))
+    }
+}
+
+/// Re-renders the project whenever its MIDI file changes. Only the MIDI file is watched: changes to
+/// the instruments need a restart, after bumping their `InstrumentKind::version`.
+///
+/// With `render_on_start`, the first render also happens in the background instead of before launch.
+pub struct ReloadPlugin {
+    pub project: Project,
+    pub sample_rate: f64,
+    pub render_on_start: bool,
+}
+
+impl Plugin for ReloadPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(Reload {
+            project: self.project.clone(),
+            sample_rate: self.sample_rate,
+            modified: if self.render_on_start {
+                None
+            } else {
+                modified_time(&self.project.path)
+            },
+            timer: Timer::from_seconds(POLL_SECS, TimerMode::Repeating),
+        })
+        .add_systems(Update, watch_project)
+        .add_systems(Update, handle_reload_task);
+        if !app.is_plugin_added::<LoadingPlugin>() {
+            app.add_plugins(LoadingPlugin);
+        }
+    }
+}
+
+#[derive(Resource)]
+struct Reload {
+    project: Project,
+    sample_rate: f64,
+    modified: Option<SystemTime>,
+    timer: Timer,
+}
+
+struct Reloaded {
+    wave: WaveResource,
+    midi: Option<MidiResource>,
+    warning: Option<AppError>,
+}
+
+#[derive(Component)]
+struct ReloadTask(Task<Result<Reloaded, AppError>>);
+
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+fn project_name(path: &Path) -> String {
+    path.file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+fn watch_project(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut reload: ResMut<Reload>,
+    pending: Query<(), With<ReloadTask>>,
+) {
+    if !reload.timer.tick(time.delta()).just_finished() || !pending.is_empty() {
+        return;
+    }
+    let modified = modified_time(&reload.project.path);
+    if modified.is_none() || modified == reload.modified {
+        return;
+    }
+    reload.modified = modified;
+
+    let project = reload.project.clone();
+    let sample_rate = reload.sample_rate;
+    info!("Rendering {}...", project.path.display());
+
+    let task = AsyncComputeTaskPool::get().spawn(async move {
+        let (mut arrangement, midi) = project.load()?;
+        let cache = render_cache_path(&project.path, &midi, &arrangement.instruments, sample_rate);
+        let (wave, warning) = get_render(&mut arrangement.daw, sample_rate, cache);
+        Ok(Reloaded {
+            wave,
+            midi: MidiResource::parse(&midi).ok(),
+            warning,
+        })
+    });
+    // Not a real progress bar: `get_render` can't measure rendering, so there is no ETA either.
+    let bars = vec![(
+        project_name(&reload.project.path),
+        ProgressBar::indeterminate(),
+    )];
+    let entity = spawn_loading_screen(&mut commands, "Rendering audio", bars);
+    commands.entity(entity).insert(ReloadTask(task));
+}
+
+fn handle_reload_task(
+    mut commands: Commands,
+    mut tasks: Query<(Entity, &mut ReloadTask)>,
+    mut playback: ResMut<PlaybackResource>,
+    mut errors: ResMut<ErrorLog>,
+) {
+    for (entity, mut task) in tasks.iter_mut() {
+        let Some(result) = block_on(future::poll_once(&mut task.0)) else {
+            continue;
+        };
+        commands.entity(entity).despawn_recursive();
+        // Editors may save in several steps, so a broken file is reported and retried on the next change.
+        let reloaded = match result {
+            Ok(reloaded) => reloaded,
+            Err(e) => {
+                errors.push_from(ErrorSource::Reload, e);
+                continue;
+            }
+        };
+        // A successful reload takes back only what earlier reloads reported.
+        errors.clear_from(ErrorSource::Reload);
+        if let Some(warning) = reloaded.warning {
+            errors.push_from(ErrorSource::Reload, warning);
+        }
+        if let Some(Err(e)) = reloaded
+            .midi
+            .as_ref()
+            .map(|midi| midi.check_track_count(reloaded.wave.channel_count()))
+        {
+            errors.push_from(ErrorSource::Reload, e);
+        }
+
+        let time = playback.elapsed();
+        let sample_rate = playback.sample_rate;
+        playback.stop();
+
+        commands.insert_resource(PlaybackResource::resume_at(sample_rate, time));
+        match reloaded.midi {
+            Some(midi) => commands.insert_resource(midi),
+            None => commands.remove_resource::<MidiResource>(),
+        }
+        commands.insert_resource(reloaded.wave);
+        info!("Reloaded, resuming at {time:.1}s");
+    }
+}
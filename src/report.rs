@@ -0,0 +1,123 @@
+use std::fmt;
+
+use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
+
+use crate::channel::ChannelData;
+
+const SECTION_SECS: f64 = 1.0;
+const WORST_SECTIONS: usize = 5;
+
+pub struct Section {
+    pub start: f64,
+    pub mean_error: f64,
+    pub fallbacks: usize,
+}
+
+pub struct ChannelReport {
+    pub name: String,
+    pub frames: usize,
+    pub mean_error: f64,
+    pub max_error: f64,
+    pub fallbacks: usize,
+    pub worst: Vec<Section>,
+}
+
+impl ChannelReport {
+    pub fn new(channel: &ChannelData, sample_rate: f64) -> Self {
+        let mut errors = Vec::new();
+        channel.trigger_frames(sample_rate, |error| errors.push(error));
+
+        let found: Vec<f64> = errors.iter().flatten().copied().collect();
+        let mean_error = mean(&found);
+        let max_error = found.iter().copied().fold(0.0, f64::max);
+        let fallbacks = errors.len() - found.len();
+
+        let frames_per_section = ((channel.target_fps * SECTION_SECS) as usize).max(1);
+        let mut sections: Vec<Section> = errors
+            .chunks(frames_per_section)
+            .enumerate()
+            .map(|(i, chunk)| {
+                let found: Vec<f64> = chunk.iter().flatten().copied().collect();
+                Section {
+                    start: i as f64 * SECTION_SECS,
+                    mean_error: mean(&found),
+                    fallbacks: chunk.len() - found.len(),
+                }
+            })
+            .collect();
+        sections.sort_by(|a, b| {
+            b.fallbacks
+                .cmp(&a.fallbacks)
+                .then(b.mean_error.total_cmp(&a.mean_error))
+        });
+        sections.truncate(WORST_SECTIONS);
+
+        Self {
+            name: channel.name.clone(),
+            frames: errors.len(),
+            mean_error,
+            max_error,
+            fallbacks,
+            worst: sections,
+        }
+    }
+}
+
+fn mean(values: &[f64]) -> f64 {
+    if values.is_empty() {
+        0.0
+    } else {
+        values.iter().sum::<f64>() / values.len() as f64
+    }
+}
+
+fn timestamp(secs: f64) -> String {
+    format!("{}:{:04.1}", (secs / 60.0) as usize, secs % 60.0)
+}
+
+/// Alignment error is the mean absolute amplitude difference per sample between consecutive
+/// triggered frames, on the same scale as the samples themselves, so lower is steadier. It is not
+/// a time offset. Fallbacks are frames where no zero crossing was found.
+pub struct TriggerReport(pub Vec<ChannelReport>);
+
+impl TriggerReport {
+    pub fn new(channels: &[ChannelData], sample_rate: f64) -> Self {
+        Self(
+            channels
+                .par_iter()
+                .map(|channel| ChannelReport::new(channel, sample_rate))
+                .collect(),
+        )
+    }
+}
+
+impl fmt::Display for TriggerReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{:<20} {:>8} {:>10} {:>10} {:>10}",
+            "Channel", "Frames", "Mean err", "Max err", "Fallbacks"
+        )?;
+        for channel in &self.0 {
+            writeln!(
+                f,
+                "{:<20} {:>8} {:>10.5} {:>10.5} {:>10}",
+                channel.name,
+                channel.frames,
+                channel.mean_error,
+                channel.max_error,
+                channel.fallbacks
+            )?;
+            for section in &channel.worst {
+                writeln!(
+                    f,
+                    "    {:>7}  err {:.5}  fallbacks {}",
+                    timestamp(section.start),
+                    section.mean_error,
+                    section.fallbacks
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
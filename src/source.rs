@@ -0,0 +1,486 @@
+use std::{
+    ops::{Deref, Range},
+    path::{Path, PathBuf},
+    sync::{Arc, OnceLock},
+};
+
+use soundmaker::{
+    daw::{render_daw, RenderedAudio, DAW},
+    prelude::Instrument,
+};
+
+use crate::{
+    cache::{write_cache, MappedSource},
+    error::AppError,
+    wave::WaveResource,
+};
+
+/// One stereo sample, left then right.
+pub type Frame = [f32; 2];
+
+/// Read-only samples shared between sources, playback and the display. Cloning shares the
+/// buffer, wherever it lives.
+pub struct SampleBuffer<T: 'static>(Arc<dyn SampleStorage<T>>);
+
+/// Where the samples of a `SampleBuffer` live.
+pub trait SampleStorage<T>: AsRef<[T]> + Send + Sync {
+    /// Hints that `range` won't be read again soon. Storage backed by a file can hand those pages
+    /// back to the OS; memory that only exists once can't, so this does nothing by default.
+    fn release(&self, _range: Range<usize>) {}
+}
+
+impl<T: Send + Sync> SampleStorage<T> for Vec<T> {}
+
+impl<T> SampleBuffer<T> {
+    pub fn new(data: impl SampleStorage<T> + 'static) -> Self {
+        Self(Arc::new(data))
+    }
+    /// See `SampleStorage::release`. Reading the range again afterwards is still fine.
+    pub fn release(&self, range: Range<usize>) {
+        self.0.release(range);
+    }
+}
+
+impl<T> Clone for SampleBuffer<T> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+impl<T: Send + Sync> Default for SampleBuffer<T> {
+    fn default() -> Self {
+        Self::new(Vec::new())
+    }
+}
+
+impl<T: Send + Sync> From<Vec<T>> for SampleBuffer<T> {
+    fn from(data: Vec<T>) -> Self {
+        Self::new(data)
+    }
+}
+
+impl<T> Deref for SampleBuffer<T> {
+    type Target = [T];
+
+    fn deref(&self) -> &[T] {
+        (*self.0).as_ref()
+    }
+}
+
+/// Audio shown by the scope. Channel `channel_count()` is the master mix.
+///
+/// Finite sources expose whole channels through `samples`; streamed sources return `None` there
+/// and hand out new frames through `read` instead.
+pub trait AudioSource: Send + Sync {
+    fn sample_rate(&self) -> f64;
+    fn channel_count(&self) -> usize;
+    fn channel_name(&self, index: usize) -> String;
+    fn samples(&self, index: usize) -> Option<&SampleBuffer<Frame>>;
+
+    fn available(&self) -> usize {
+        0
+    }
+    fn read(&self, _frames: usize) -> Vec<Vec<Frame>> {
+        Vec::new()
+    }
+    /// The channel mixed down to mono, which is what the trigger and the trace work on. This
+    /// mixes it down on every call, so sources that keep their samples should cache it.
+    fn mono(&self, index: usize) -> SampleBuffer<f32> {
+        mixdown(self.samples(index).map_or(&[][..], |s| s))
+    }
+
+    fn channel_names(&self) -> Vec<String> {
+        (0..self.channel_count())
+            .map(|i| self.channel_name(i))
+            .collect()
+    }
+    fn master(&self) -> SampleBuffer<Frame> {
+        self.samples(self.channel_count())
+            .cloned()
+            .unwrap_or_default()
+    }
+    fn duration(&self) -> f64 {
+        self.master().len() as f64 / self.sample_rate()
+    }
+    fn stereo_window(&self, index: usize, end: usize, len: usize) -> &[Frame] {
+        let data = self.samples(index).map_or(&[][..], |s| s);
+        let end = end.min(data.len());
+        &data[end.saturating_sub(len)..end]
+    }
+}
+
+fn mixdown(samples: &[Frame]) -> SampleBuffer<f32> {
+    samples
+        .iter()
+        .map(|[left, right]| (left + right) / 2.0)
+        .collect::<Vec<_>>()
+        .into()
+}
+
+pub struct BufferedSource {
+    pub master: SampleBuffer<Frame>,
+    pub channels: Vec<SampleBuffer<Frame>>,
+    pub channel_names: Vec<String>,
+    pub sample_rate: f64,
+    mono: OnceLock<Vec<SampleBuffer<f32>>>,
+}
+
+impl BufferedSource {
+    pub fn new(
+        master: SampleBuffer<Frame>,
+        channels: Vec<SampleBuffer<Frame>>,
+        channel_names: Vec<String>,
+        sample_rate: f64,
+    ) -> Self {
+        Self {
+            master,
+            channels,
+            channel_names,
+            sample_rate,
+            mono: OnceLock::new(),
+        }
+    }
+}
+
+impl AudioSource for BufferedSource {
+    fn sample_rate(&self) -> f64 {
+        self.sample_rate
+    }
+    fn channel_count(&self) -> usize {
+        self.channels.len()
+    }
+    fn channel_name(&self, index: usize) -> String {
+        self.channel_names[index].clone()
+    }
+    fn samples(&self, index: usize) -> Option<&SampleBuffer<Frame>> {
+        if index == self.channel_count() {
+            return Some(&self.master);
+        }
+        self.channels.get(index)
+    }
+    fn mono(&self, index: usize) -> SampleBuffer<f32> {
+        // Every channel is mixed down on first use and shared from then on.
+        let mono = self.mono.get_or_init(|| {
+            (0..=self.channel_count())
+                .map(|i| mixdown(self.samples(i).map_or(&[][..], |s| s)))
+                .collect()
+        });
+        mono.get(index).cloned().unwrap_or_default()
+    }
+}
+
+/// Converts a soundmaker render to f32 frames, halving what it takes to keep around.
+pub struct RenderedSource(BufferedSource);
+
+impl RenderedSource {
+    pub fn new(audio: RenderedAudio, channel_names: Vec<String>, sample_rate: f64) -> Self {
+        let to_frames = |samples: Vec<(f64, f64)>| -> SampleBuffer<Frame> {
+            let frames: Vec<Frame> = samples
+                .into_iter()
+                .map(|(left, right)| [left as f32, right as f32])
+                .collect();
+            frames.into()
+        };
+        let channel_names = (0..audio.channels.len())
+            .map(|i| {
+                channel_names
+                    .get(i)
+                    .cloned()
+                    .unwrap_or_else(|| format!("Channel {}", i + 1))
+            })
+            .collect();
+        Self(BufferedSource::new(
+            to_frames(audio.master),
+            audio.channels.into_iter().map(to_frames).collect(),
+            channel_names,
+            sample_rate,
+        ))
+    }
+}
+
+impl AudioSource for RenderedSource {
+    fn sample_rate(&self) -> f64 {
+        self.0.sample_rate()
+    }
+    fn channel_count(&self) -> usize {
+        self.0.channel_count()
+    }
+    fn channel_name(&self, index: usize) -> String {
+        self.0.channel_name(index)
+    }
+    fn samples(&self, index: usize) -> Option<&SampleBuffer<Frame>> {
+        self.0.samples(index)
+    }
+    fn mono(&self, index: usize) -> SampleBuffer<f32> {
+        self.0.mono(index)
+    }
+}
+
+pub fn daw_channel_names(daw: &DAW) -> Vec<String> {
+    (0..daw.channel_count)
+        .map(|i| daw[i].name.clone())
+        .collect()
+}
+
+/// Which instrument a channel plays, for the render cache key. soundmaker doesn't expose an
+/// instrument's parameters, so `version` stands in for them: bump it whenever the instrument
+/// changes, or renders made with the old one keep being loaded from the cache.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct InstrumentKind {
+    pub name: &'static str,
+    pub version: u32,
+}
+
+/// How an instrument was added. The DAW doesn't expose this afterwards, so it is kept for the
+/// render cache key.
+#[derive(Clone, Debug, PartialEq)]
+pub struct InstrumentSetup {
+    pub name: String,
+    pub kind: InstrumentKind,
+    pub volume: f64,
+    pub pan: f64,
+}
+
+/// A DAW along with how each of its instruments was set up.
+pub struct Arrangement {
+    pub daw: DAW,
+    pub instruments: Vec<InstrumentSetup>,
+}
+
+impl Arrangement {
+    pub fn new() -> Self {
+        Self {
+            daw: DAW::new(),
+            instruments: Vec::new(),
+        }
+    }
+    pub fn add_instrument(
+        &mut self,
+        name: String,
+        kind: InstrumentKind,
+        instrument: &dyn Instrument,
+        volume: f64,
+        pan: f64,
+    ) {
+        self.daw
+            .add_instrument(name.clone(), instrument, volume, pan);
+        self.instruments.push(InstrumentSetup {
+            name,
+            kind,
+            volume,
+            pan,
+        });
+    }
+}
+
+impl Default for Arrangement {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// 64-bit FNV-1a, which unlike `DefaultHasher` gives the same cache names on every build.
+struct Fnv(u64);
+
+impl Fnv {
+    fn new() -> Self {
+        Self(0xcbf2_9ce4_8422_2325)
+    }
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 = (self.0 ^ byte as u64).wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+    fn write_bytes(&mut self, bytes: &[u8]) {
+        self.write(&(bytes.len() as u64).to_le_bytes());
+        self.write(bytes);
+    }
+}
+
+/// `./output/render-<project>-<render>.scope`. The first hash names the project, the second
+/// everything its render depends on, so renders of the same project that it supersedes can be
+/// found and removed.
+pub fn render_cache_path(
+    project: &Path,
+    midi: &[u8],
+    instruments: &[InstrumentSetup],
+    sample_rate: f64,
+) -> PathBuf {
+    let mut project_hash = Fnv::new();
+    let project = project.canonicalize().unwrap_or(project.to_path_buf());
+    project_hash.write_bytes(project.to_string_lossy().as_bytes());
+
+    let mut render_hash = Fnv::new();
+    render_hash.write_bytes(midi);
+    render_hash.write(&sample_rate.to_le_bytes());
+    for instrument in instruments {
+        render_hash.write_bytes(instrument.name.as_bytes());
+        render_hash.write_bytes(instrument.kind.name.as_bytes());
+        render_hash.write(&instrument.kind.version.to_le_bytes());
+        render_hash.write(&instrument.volume.to_le_bytes());
+        render_hash.write(&instrument.pan.to_le_bytes());
+    }
+    PathBuf::from(format!(
+        "./output/render-{:08x}-{:016x}.scope",
+        project_hash.0 as u32, render_hash.0
+    ))
+}
+
+/// Maps the cached render, or renders and caches it. If the cache can't be written, the render
+/// is kept in memory and the error returned alongside it.
+///
+/// Rendering can't report progress: soundmaker's `render_daw` renders every channel in one call
+/// and exposes neither a progress callback nor a way to render a single channel, so loading
+/// screens can only show that it is still running and for how long.
+pub fn get_render(
+    daw: &mut DAW,
+    sample_rate: f64,
+    file_path: PathBuf,
+) -> (WaveResource, Option<AppError>) {
+    let channel_names = daw_channel_names(daw);
+    if let Ok(cached) = MappedSource::open_at(&file_path, channel_names.clone(), sample_rate) {
+        return (WaveResource::new(cached), None);
+    }
+    let render = RenderedSource::new(
+        render_daw(daw, sample_rate),
+        channel_names.clone(),
+        sample_rate,
+    );
+    match save_render(&render, &file_path)
+        .and_then(|()| MappedSource::open_at(&file_path, channel_names, sample_rate))
+    {
+        Ok(cached) => (WaveResource::new(cached), None),
+        Err(e) => (WaveResource::new(render), Some(e)),
+    }
+}
+
+pub fn save_render(source: &dyn AudioSource, file_path: &Path) -> Result<(), AppError> {
+    if let Some(dir) = file_path.parent() {
+        std::fs::create_dir_all(dir)
+            .map_err(|e| AppError::OutputNotWritable(file_path.to_path_buf(), e.to_string()))?;
+    }
+    write_cache(source, file_path)?;
+    remove_superseded(file_path);
+    Ok(())
+}
+
+/// Removes the other renders of the project `file_path` belongs to.
+fn remove_superseded(file_path: &Path) {
+    let (Some(dir), Some(name)) = (
+        file_path.parent(),
+        file_path.file_name().and_then(|name| name.to_str()),
+    ) else {
+        return;
+    };
+    let Some(project) = name.rfind('-').map(|i| &name[..=i]) else {
+        return;
+    };
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let other = entry.file_name();
+        let other = other.to_string_lossy();
+        if other != name && other.starts_with(project) && other.ends_with(".scope") {
+            // A stale render that can't be removed only takes up disk space.
+            std::fs::remove_file(entry.path()).ok();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn setup(volume: f64, version: u32) -> Vec<InstrumentSetup> {
+        let kind = InstrumentKind {
+            name: "piano",
+            version,
+        };
+        vec![InstrumentSetup {
+            name: "Piano".into(),
+            kind,
+            volume,
+            pan: 0.0,
+        }]
+    }
+
+    #[test]
+    fn cache_path_covers_everything_the_render_depends_on() {
+        let project = Path::new("song.mid");
+        let path = render_cache_path(project, b"midi", &setup(1.0, 1), 44100.0);
+
+        assert_eq!(
+            path,
+            render_cache_path(project, b"midi", &setup(1.0, 1), 44100.0)
+        );
+        assert_ne!(
+            path,
+            render_cache_path(project, b"midi", &setup(1.0, 1), 48000.0)
+        );
+        assert_ne!(
+            path,
+            render_cache_path(project, b"midi", &setup(0.5, 1), 44100.0)
+        );
+        assert_ne!(
+            path,
+            render_cache_path(project, b"midj", &setup(1.0, 1), 44100.0)
+        );
+        assert_ne!(
+            path,
+            render_cache_path(project, b"midi", &setup(1.0, 2), 44100.0)
+        );
+    }
+
+    #[test]
+    fn mono_is_mixed_down_once() {
+        let source = BufferedSource::new(
+            vec![[1.0, 0.0]; 4].into(),
+            vec![vec![[0.5, 0.5]; 4].into()],
+            vec!["A".into()],
+            44100.0,
+        );
+
+        assert_eq!(*source.mono(0), [0.5; 4]);
+        assert_eq!(*source.mono(1), [0.5; 4]);
+        assert!(std::ptr::eq(
+            source.mono(1).as_ptr(),
+            source.mono(1).as_ptr()
+        ));
+    }
+
+    #[test]
+    fn only_the_index_after_the_channels_is_the_master() {
+        let source = BufferedSource::new(
+            vec![[1.0, 0.0]; 4].into(),
+            vec![vec![[0.5, 0.5]; 4].into()],
+            vec!["A".into()],
+            44100.0,
+        );
+
+        assert_eq!(**source.samples(0).unwrap(), [[0.5, 0.5]; 4]);
+        assert_eq!(**source.samples(1).unwrap(), [[1.0, 0.0]; 4]);
+        assert!(source.samples(2).is_none());
+        assert!(source.mono(2).is_empty());
+        assert!(source.stereo_window(2, 4, 4).is_empty());
+    }
+
+    #[test]
+    fn saving_a_render_removes_the_ones_it_supersedes() {
+        let dir = std::env::temp_dir().join(format!("oscilloscope-{}-renders", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let source =
+            BufferedSource::new(vec![[0.5; 2]; 16].into(), Vec::new(), Vec::new(), 44100.0);
+        let old = dir.join("render-0000000a-0000000000000001.scope");
+        let other_project = dir.join("render-0000000b-0000000000000001.scope");
+        let new = dir.join("render-0000000a-0000000000000002.scope");
+        save_render(&source, &old).unwrap();
+        save_render(&source, &other_project).unwrap();
+        save_render(&source, &new).unwrap();
+
+        assert!(!old.exists());
+        assert!(other_project.exists());
+        assert!(new.exists());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,171 @@
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+};
+
+use crate::source::{BufferedSource, Frame};
+
+const MIXDOWN_NAMES: [&str; 3] = ["mix", "mixdown", "master"];
+
+#[derive(Debug)]
+pub enum StemError {
+    Io(std::io::Error),
+    Wav(PathBuf, hound::Error),
+    Flac(PathBuf, claxon::Error),
+    SampleRateMismatch(PathBuf, u32, u32),
+    NoStems(PathBuf),
+}
+
+impl fmt::Display for StemError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StemError::Io(e) => write!(f, "could not read stem folder: {e}"),
+            StemError::Wav(path, e) => write!(f, "could not decode {}: {e}", path.display()),
+            StemError::Flac(path, e) => write!(f, "could not decode {}: {e}", path.display()),
+            StemError::SampleRateMismatch(path, expected, found) => write!(
+                f,
+                "{} has sample rate {found} Hz, expected {expected} Hz",
+                path.display()
+            ),
+            StemError::NoStems(dir) => write!(f, "no WAV or FLAC files in {}", dir.display()),
+        }
+    }
+}
+
+impl From<std::io::Error> for StemError {
+    fn from(e: std::io::Error) -> Self {
+        StemError::Io(e)
+    }
+}
+
+struct Stem {
+    name: String,
+    sample_rate: u32,
+    samples: Vec<Frame>,
+}
+
+pub fn load_stems(dir: &Path) -> Result<BufferedSource, StemError> {
+    let mut paths: Vec<PathBuf> = std::fs::read_dir(dir)?
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<Result<_, _>>()?;
+    paths.sort();
+
+    let mut stems: Vec<Stem> = Vec::new();
+    for path in paths {
+        let extension = path.extension().map(|e| e.to_string_lossy().to_lowercase());
+        let stem = match extension.as_deref() {
+            Some("wav") => read_wav(&path)?,
+            Some("flac") => read_flac(&path)?,
+            _ => continue,
+        };
+        if let Some(first) = stems.first() {
+            if first.sample_rate != stem.sample_rate {
+                return Err(StemError::SampleRateMismatch(
+                    path,
+                    first.sample_rate,
+                    stem.sample_rate,
+                ));
+            }
+        }
+        stems.push(stem);
+    }
+
+    let Some(sample_rate) = stems.first().map(|s| s.sample_rate as f64) else {
+        return Err(StemError::NoStems(dir.to_path_buf()));
+    };
+    let len = stems.iter().map(|s| s.samples.len()).max().unwrap_or(0);
+
+    let mixdown = stems
+        .iter()
+        .position(|s| MIXDOWN_NAMES.contains(&s.name.to_lowercase().as_str()))
+        .map(|i| stems.remove(i));
+
+    let mut channel_names = Vec::new();
+    let mut channels = Vec::new();
+    for mut stem in stems {
+        stem.samples.resize(len, [0.0; 2]);
+        channel_names.push(stem.name);
+        channels.push(stem.samples);
+    }
+
+    let master = match mixdown {
+        Some(mut mixdown) => {
+            mixdown.samples.resize(len, [0.0; 2]);
+            mixdown.samples
+        }
+        None => (0..len)
+            .map(|i| {
+                channels
+                    .iter()
+                    .fold([0.0; 2], |acc, c| [acc[0] + c[i][0], acc[1] + c[i][1]])
+            })
+            .collect(),
+    };
+
+    Ok(BufferedSource::new(
+        master.into(),
+        channels.into_iter().map(Into::into).collect(),
+        channel_names,
+        sample_rate,
+    ))
+}
+
+fn stem_name(path: &Path) -> String {
+    path.file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_default()
+}
+
+fn interleaved_to_stereo(samples: Vec<f32>, channels: usize) -> Vec<Frame> {
+    samples
+        .chunks_exact(channels.max(1))
+        .map(|frame| match frame {
+            [mono] => [*mono; 2],
+            [left, right, ..] => [*left, *right],
+            [] => [0.0; 2],
+        })
+        .collect()
+}
+
+fn read_wav(path: &Path) -> Result<Stem, StemError> {
+    let wav_error = |e| StemError::Wav(path.to_path_buf(), e);
+    let mut reader = hound::WavReader::open(path).map_err(wav_error)?;
+    let spec = reader.spec();
+
+    let samples: Vec<f32> = match spec.sample_format {
+        hound::SampleFormat::Float => reader.samples::<f32>().collect::<Result<_, _>>(),
+        hound::SampleFormat::Int => {
+            let scale = 1.0 / (1u64 << (spec.bits_per_sample - 1)) as f32;
+            reader
+                .samples::<i32>()
+                .map(|s| s.map(|s| s as f32 * scale))
+                .collect::<Result<_, _>>()
+        }
+    }
+    .map_err(wav_error)?;
+
+    Ok(Stem {
+        name: stem_name(path),
+        sample_rate: spec.sample_rate,
+        samples: interleaved_to_stereo(samples, spec.channels as usize),
+    })
+}
+
+fn read_flac(path: &Path) -> Result<Stem, StemError> {
+    let flac_error = |e| StemError::Flac(path.to_path_buf(), e);
+    let mut reader = claxon::FlacReader::open(path).map_err(flac_error)?;
+    let info = reader.streaminfo();
+    let scale = 1.0 / (1u64 << (info.bits_per_sample - 1)) as f32;
+
+    let samples: Vec<f32> = reader
+        .samples()
+        .map(|s| s.map(|s| s as f32 * scale))
+        .collect::<Result<_, _>>()
+        .map_err(flac_error)?;
+
+    Ok(Stem {
+        name: stem_name(path),
+        sample_rate: info.sample_rate,
+        samples: interleaved_to_stereo(samples, info.channels as usize),
+    })
+}
@@ -0,0 +1,250 @@
+use bevy::prelude::*;
+use bevy_prototype_lyon::prelude::*;
+
+use crate::{
+    channel::{ChannelData, StripElement},
+    meter::METER_WIDTH,
+    source::Frame,
+    wave::{PlaybackResource, WaveResource},
+};
+
+const GONIOMETER_SAMPLES: usize = 1024;
+
+pub struct StereoPlugin;
+
+impl Plugin for StereoPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, update_goniometers)
+            .add_systems(Update, toggle_stem_goniometers);
+    }
+}
+
+#[derive(Component)]
+pub struct Goniometer {
+    index: usize,
+    rect: Rect,
+}
+
+#[derive(Component)]
+enum GoniometerPart {
+    Frame,
+    Trace,
+}
+
+#[derive(Component)]
+struct CorrelationText;
+
+#[derive(Component)]
+struct StemGoniometer;
+
+pub fn spawn_goniometer(commands: &mut Commands, data: &ChannelData, is_master: bool) {
+    let visibility = if is_master {
+        Visibility::Visible
+    } else {
+        Visibility::Hidden
+    };
+    let goniometer = || Goniometer {
+        index: data.index,
+        rect: data.position,
+    };
+
+    let entities = [
+        commands
+            .spawn((
+                StripElement,
+                goniometer(),
+                GoniometerPart::Frame,
+                ShapeBundle {
+                    path: PathBuilder::new().build(),
+                    spatial: SpatialBundle {
+                        transform: Transform::from_xyz(0.0, 0.0, 0.5),
+                        visibility,
+                        ..default()
+                    },
+                    ..default()
+                },
+                Stroke::new(Color::hex("444d56").unwrap(), 1.0),
+                Fill::color(Color::NONE),
+            ))
+            .id(),
+        commands
+            .spawn((
+                StripElement,
+                goniometer(),
+                GoniometerPart::Trace,
+                ShapeBundle {
+                    path: PathBuilder::new().build(),
+                    spatial: SpatialBundle {
+                        transform: Transform::from_xyz(0.0, 0.0, 0.6),
+                        visibility,
+                        ..default()
+                    },
+                    ..default()
+                },
+                Stroke::new(Color::hex("c678dd").unwrap(), 1.0),
+                Fill::color(Color::NONE),
+            ))
+            .id(),
+        commands
+            .spawn((
+                StripElement,
+                goniometer(),
+                CorrelationText,
+                TextBundle {
+                    text: Text::from_section(
+                        "",
+                        TextStyle {
+                            font_size: 12.0,
+                            color: Color::WHITE,
+                            ..default()
+                        },
+                    ),
+                    style: Style {
+                        position_type: PositionType::Absolute,
+                        ..default()
+                    },
+                    visibility,
+                    ..default()
+                },
+            ))
+            .id(),
+    ];
+
+    if !is_master {
+        for entity in entities {
+            commands.entity(entity).insert(StemGoniometer);
+        }
+    }
+}
+
+fn scope_layout(rect: Rect, size: Vec2) -> (Vec2, f32, f32) {
+    let strip_height = rect.height() * size.y;
+    let half = strip_height * 0.35;
+    let center = Vec2::new(
+        rect.max.x * size.x - (METER_WIDTH + 70.0) - half,
+        rect.center().y * size.y + strip_height * 0.07,
+    );
+    let bar_y = center.y - half - strip_height * 0.08;
+    (center, half, bar_y)
+}
+
+pub fn correlation(samples: &[Frame]) -> Option<f64> {
+    let (lr, ll, rr) = samples
+        .iter()
+        .fold((0.0, 0.0, 0.0), |(lr, ll, rr), &[l, r]| {
+            let (l, r) = (l as f64, r as f64);
+            (lr + l * r, ll + l * l, rr + r * r)
+        });
+    let denom = (ll * rr).sqrt();
+    if denom <= f64::EPSILON {
+        None
+    } else {
+        Some((lr / denom).clamp(-1.0, 1.0))
+    }
+}
+
+fn update_goniometers(
+    window: Query<&Window>,
+    wave: Res<WaveResource>,
+    playback: Res<PlaybackResource>,
+    mut paths: Query<(
+        &Goniometer,
+        &GoniometerPart,
+        &mut Path,
+        &InheritedVisibility,
+    )>,
+    mut texts: Query<(&Goniometer, &mut Text, &mut Style), With<CorrelationText>>,
+) {
+    let Ok(w) = window.get_single() else {
+        return;
+    };
+    let size = Vec2::new(w.width(), w.height());
+    let end = (playback.elapsed() * playback.sample_rate) as usize;
+
+    for (goniometer, part, mut path, visibility) in paths.iter_mut() {
+        if !visibility.get() {
+            continue;
+        }
+        let (center, half, bar_y) = scope_layout(goniometer.rect, size);
+        let mut path_builder = PathBuilder::new();
+
+        match part {
+            GoniometerPart::Frame => {
+                for (from, to) in [
+                    (Vec2::new(-1.0, 0.0), Vec2::new(0.0, 1.0)),
+                    (Vec2::new(0.0, 1.0), Vec2::new(1.0, 0.0)),
+                    (Vec2::new(1.0, 0.0), Vec2::new(0.0, -1.0)),
+                    (Vec2::new(0.0, -1.0), Vec2::new(-1.0, 0.0)),
+                    (Vec2::new(0.0, -1.0), Vec2::new(0.0, 1.0)),
+                    (Vec2::new(-0.5, 0.5), Vec2::new(0.5, -0.5)),
+                    (Vec2::new(-0.5, -0.5), Vec2::new(0.5, 0.5)),
+                ] {
+                    path_builder.move_to(center + from * half);
+                    path_builder.line_to(center + to * half);
+                }
+                path_builder.move_to(Vec2::new(center.x - half, bar_y));
+                path_builder.line_to(Vec2::new(center.x + half, bar_y));
+                path_builder.move_to(Vec2::new(center.x, bar_y - 3.0));
+                path_builder.line_to(Vec2::new(center.x, bar_y + 3.0));
+            }
+            GoniometerPart::Trace => {
+                let samples = wave.stereo_window(goniometer.index, end, GONIOMETER_SAMPLES);
+                // Rotate by 45 degrees so mid is vertical and side is horizontal.
+                let mut points = samples.iter().map(|&[l, r]| {
+                    let side = ((r - l) * std::f32::consts::FRAC_1_SQRT_2).clamp(-1.0, 1.0);
+                    let mid = ((l + r) * std::f32::consts::FRAC_1_SQRT_2).clamp(-1.0, 1.0);
+                    center + Vec2::new(side, mid) * half
+                });
+                if let Some(first) = points.next() {
+                    path_builder.move_to(first);
+                    for point in points {
+                        path_builder.line_to(point);
+                    }
+                }
+
+                if let Some(r) = correlation(samples) {
+                    let x = center.x + r as f32 * half;
+                    path_builder.move_to(Vec2::new(x, bar_y - 5.0));
+                    path_builder.line_to(Vec2::new(x, bar_y + 5.0));
+                }
+            }
+        }
+        *path = path_builder.build();
+    }
+
+    for (goniometer, mut text, mut style) in texts.iter_mut() {
+        let (center, half, bar_y) = scope_layout(goniometer.rect, size);
+        style.left = Val::Px(center.x - half - 42.0 + size.x / 2.0);
+        style.top = Val::Px(size.y / 2.0 - bar_y - 7.0);
+
+        let samples = wave.stereo_window(goniometer.index, end, GONIOMETER_SAMPLES);
+        let section = &mut text.sections[0];
+        if let Some(r) = correlation(samples) {
+            section.value = format!("{r:+.2}");
+            section.style.color = if r >= 0.5 {
+                Color::hex("98c379").unwrap()
+            } else if r >= 0.0 {
+                Color::hex("e5c07b").unwrap()
+            } else {
+                Color::hex("e06c75").unwrap()
+            };
+        } else {
+            section.value = "--".to_string();
+            section.style.color = Color::WHITE;
+        }
+    }
+}
+
+fn toggle_stem_goniometers(
+    mut q: Query<&mut Visibility, With<StemGoniometer>>,
+    kbd: Res<ButtonInput<KeyCode>>,
+) {
+    if kbd.just_pressed(KeyCode::KeyS) {
+        for mut vis in q.iter_mut() {
+            *vis = match *vis {
+                Visibility::Hidden => Visibility::Visible,
+                _ => Visibility::Hidden,
+            };
+        }
+    }
+}
@@ -0,0 +1,49 @@
+//! Deterministic signals for the trigger and path tests.
+
+use std::f64::consts::TAU;
+
+pub const SAMPLE_RATE: f64 = 44100.0;
+
+pub fn sine(frequency: f64, len: usize) -> Vec<f32> {
+    (0..len)
+        .map(|i| (TAU * frequency * i as f64 / SAMPLE_RATE).sin() as f32)
+        .collect()
+}
+
+pub fn square(period: usize, len: usize) -> Vec<f32> {
+    (0..len)
+        .map(|i| if i % period < period / 2 { 1.0 } else { -1.0 })
+        .collect()
+}
+
+pub fn saw(period: usize, len: usize) -> Vec<f32> {
+    (0..len)
+        .map(|i| 2.0 * (i % period) as f32 / period as f32 - 1.0)
+        .collect()
+}
+
+pub fn noise(rng: &mut Rng, len: usize) -> Vec<f32> {
+    (0..len).map(|_| rng.range(-1.0, 1.0) as f32).collect()
+}
+
+/// xorshift64, so failures reproduce from the seed alone.
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Self(seed.wrapping_mul(0x9E37_79B9_7F4A_7C15) | 1)
+    }
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+    pub fn range(&mut self, min: f64, max: f64) -> f64 {
+        let unit = (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64;
+        min + (max - min) * unit
+    }
+    pub fn below(&mut self, max: usize) -> usize {
+        (self.next_u64() % max as u64) as usize
+    }
+}
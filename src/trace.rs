@@ -0,0 +1,95 @@
+use bevy::{
+    prelude::*,
+    render::{
+        mesh::{PrimitiveTopology, VertexAttributeValues},
+        render_asset::RenderAssetUsages,
+    },
+    sprite::MaterialMesh2dBundle,
+};
+use bevy_prototype_lyon::prelude::*;
+
+use crate::channel::ChannelData;
+
+const TRACE_COLOR: &str = "6cb8ff";
+
+/// How channel traces are drawn. `Mesh` writes every frame straight into a line-strip mesh, one
+/// vertex per pixel column. `Lyon` simplifies each trace into a path and tessellates it.
+#[derive(Resource, Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum TraceRenderer {
+    #[default]
+    Mesh,
+    Lyon,
+}
+
+impl TraceRenderer {
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value {
+            "mesh" => Ok(TraceRenderer::Mesh),
+            "lyon" => Ok(TraceRenderer::Lyon),
+            other => Err(format!("trace renderer must be mesh or lyon, got {other}")),
+        }
+    }
+}
+
+pub struct TracePlugin;
+
+impl Plugin for TracePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<TraceRenderer>()
+            .add_systems(Update, attach_traces);
+    }
+}
+
+/// The line-strip mesh a channel's trace is written into.
+#[derive(Component)]
+pub struct TraceMesh(Handle<Mesh>);
+
+impl TraceMesh {
+    pub fn positions<'a>(&self, meshes: &'a mut Assets<Mesh>) -> Option<&'a mut Vec<[f32; 3]>> {
+        match meshes
+            .get_mut(&self.0)?
+            .attribute_mut(Mesh::ATTRIBUTE_POSITION)?
+        {
+            VertexAttributeValues::Float32x3(positions) => Some(positions),
+            _ => None,
+        }
+    }
+}
+
+fn attach_traces(
+    mut commands: Commands,
+    renderer: Res<TraceRenderer>,
+    channels: Query<Entity, Added<ChannelData>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+) {
+    let color = Color::hex(TRACE_COLOR).unwrap();
+    for entity in channels.iter() {
+        match *renderer {
+            TraceRenderer::Lyon => {
+                commands.entity(entity).insert((
+                    ShapeBundle::default(),
+                    Stroke::new(color, 1.0),
+                    Fill::color(Color::NONE),
+                ));
+            }
+            TraceRenderer::Mesh => {
+                // Kept on the CPU too, so the positions can be rewritten in place every frame.
+                let mesh = Mesh::new(
+                    PrimitiveTopology::LineStrip,
+                    RenderAssetUsages::MAIN_WORLD | RenderAssetUsages::RENDER_WORLD,
+                )
+                .with_inserted_attribute(Mesh::ATTRIBUTE_POSITION, vec![[0.0; 3]; 2]);
+                let mesh = meshes.add(mesh);
+                commands.entity(entity).insert((
+                    MaterialMesh2dBundle {
+                        mesh: mesh.clone().into(),
+                        material: materials.add(color),
+                        ..default()
+                    },
+                    TraceMesh(mesh),
+                ));
+            }
+        }
+    }
+}
@@ -0,0 +1,84 @@
+use bevy::prelude::*;
+
+use crate::{midi::MidiResource, wave::PlaybackResource};
+
+pub struct TransportPlugin;
+
+impl Plugin for TransportPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Startup, setup_transport)
+            .add_systems(Update, update_transport_text)
+            .add_systems(Update, seek_by_bar);
+    }
+}
+
+#[derive(Component)]
+struct TransportText;
+
+fn setup_transport(mut commands: Commands) {
+    commands.spawn((
+        TransportText,
+        TextBundle {
+            text: Text::from_section(
+                "",
+                TextStyle {
+                    font_size: 16.0,
+                    color: Color::WHITE,
+                    ..default()
+                },
+            ),
+            background_color: BackgroundColor(Color::BLACK.with_a(0.5)),
+            z_index: ZIndex::Global(i32::MAX),
+            style: Style {
+                position_type: PositionType::Absolute,
+                right: Val::Percent(1.0),
+                top: Val::Percent(1.0),
+                margin: UiRect::right(Val::Px(100.0)),
+                padding: UiRect::all(Val::Px(4.0)),
+                ..default()
+            },
+            ..default()
+        },
+    ));
+}
+
+fn update_transport_text(
+    midi: Option<Res<MidiResource>>,
+    playback: Res<PlaybackResource>,
+    mut query: Query<(&mut Text, &mut Visibility), With<TransportText>>,
+) {
+    for (mut text, mut vis) in query.iter_mut() {
+        let Some(midi) = &midi else {
+            *vis = Visibility::Hidden;
+            continue;
+        };
+        let elapsed = playback.elapsed();
+        let position = midi.timeline.position(elapsed);
+        text.sections[0].value = format!(
+            "{}:{}:{:03}  {:.1} BPM",
+            position.bar,
+            position.beat,
+            position.tick,
+            midi.timeline.bpm(elapsed)
+        );
+    }
+}
+
+fn seek_by_bar(
+    midi: Option<Res<MidiResource>>,
+    mut playback: ResMut<PlaybackResource>,
+    kbd: Res<ButtonInput<KeyCode>>,
+) {
+    let Some(midi) = midi else {
+        return;
+    };
+    let bar = midi.timeline.position(playback.elapsed()).bar;
+    if kbd.just_pressed(KeyCode::BracketLeft) {
+        let time = midi.timeline.bar_seconds(bar.saturating_sub(1));
+        playback.set_time(time);
+    }
+    if kbd.just_pressed(KeyCode::BracketRight) {
+        let time = midi.timeline.bar_seconds(bar + 1);
+        playback.set_time(time);
+    }
+}
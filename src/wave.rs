@@ -1,12 +1,19 @@
 use std::{
+    ops::Deref,
+    path::PathBuf,
     sync::mpsc::channel,
     time::{Duration, Instant},
 };
 
-use bevy::{input::keyboard::KeyboardInput, prelude::*, window::close_on_esc};
-use soundmaker::prelude::*;
+use bevy::{input::keyboard::KeyboardInput, prelude::*};
 
-use crate::channel::*;
+use crate::{
+    channel::*,
+    error::{AppError, ErrorLog},
+    player::{play, save_wav, PlayerControls},
+    plugin::Overlays,
+    source::{AudioSource, Frame, SampleBuffer},
+};
 use std::thread;
 
 pub struct WavePlugin(pub f64);
@@ -14,50 +21,68 @@ pub struct WavePlugin(pub f64);
 impl Plugin for WavePlugin {
     fn build(&self, app: &mut App) {
         app.insert_resource(PlaybackResource::new(self.0))
-            .add_systems(Startup, setup_channels)
-            .add_systems(Update, close_on_esc)
+            .init_resource::<Overlays>()
+            .init_resource::<FrameRate>()
+            .init_resource::<EndBehavior>()
+            .init_resource::<Gain>()
+            .init_resource::<ErrorLog>()
+            .init_resource::<PlaybackOutput>()
+            .add_systems(
+                Update,
+                (despawn_strips, setup_channels)
+                    .chain()
+                    .run_if(resource_changed::<WaveResource>),
+            )
             .add_systems(Update, handle_tasks)
+            .add_systems(Update, apply_gain.before(update_channel))
             .add_systems(Update, update_channel)
-            .add_systems(Update, handle_pause_playback);
+            .add_systems(Update, loop_playback);
     }
 }
 
-#[derive(Resource)]
-pub struct WaveResource {
-    pub master: Vec<(f64, f64)>,
-    pub channels: Vec<Vec<(f64, f64)>>,
-    pub channel_names: Vec<String>,
+/// Space pauses, R restarts and the up and down arrows change the volume.
+pub struct PlaybackKeysPlugin;
+
+impl Plugin for PlaybackKeysPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, handle_pause_playback);
+    }
 }
 
+#[derive(Resource)]
+pub struct WaveResource(Box<dyn AudioSource>);
+
 impl WaveResource {
-    pub fn new(
-        master: Vec<(f64, f64)>,
-        channels: Vec<Vec<(f64, f64)>>,
-        channel_names: Vec<String>,
-    ) -> Self {
-        Self {
-            master,
-            channels,
-            channel_names,
-        }
+    pub fn new(source: impl AudioSource + 'static) -> Self {
+        Self(Box::new(source))
     }
 }
 
-impl From<(RenderedAudio, DAW)> for WaveResource {
-    fn from(data: (RenderedAudio, DAW)) -> Self {
-        let channel_names = (0..data.1.channel_count)
-            .map(|i| data.1[i].name.clone())
-            .collect();
-        Self::new(data.0.master, data.0.channels, channel_names)
+impl Deref for WaveResource {
+    type Target = dyn AudioSource;
+
+    fn deref(&self) -> &Self::Target {
+        self.0.as_ref()
     }
 }
 
+/// Where playback sends the song besides the clock. Audio plays on the default output device,
+/// and the recording saves the master mix as a WAV file. Reading the whole song for it defeats the
+/// lazily loaded render cache, so both are off unless asked for.
+#[derive(Resource, Clone, Default)]
+pub struct PlaybackOutput {
+    pub audio: bool,
+    pub recording: Option<PathBuf>,
+}
+
 #[derive(Resource)]
 pub struct PlaybackResource {
     pub sample_rate: f64,
     start_instant: Option<Instant>,
-    controller: Option<(Shared<f32>, Shared<f64>, Shared<f64>)>,
+    controller: Option<PlayerControls>,
     paused_time: Option<f64>,
+    resume_time: f64,
+    duration: Option<f64>,
 }
 
 impl PlaybackResource {
@@ -67,29 +92,50 @@ impl PlaybackResource {
             start_instant: None,
             controller: None,
             paused_time: None,
+            resume_time: 0.0,
+            duration: None,
         }
     }
+    pub fn resume_at(sample_rate: f64, time: f64) -> Self {
+        let mut playback = Self::new(sample_rate);
+        playback.resume_time = time;
+        playback
+    }
     pub fn elapsed(&self) -> f64 {
-        if let Some(paused_time) = self.paused_time {
+        let elapsed = if let Some(paused_time) = self.paused_time {
             paused_time
         } else if let Some(start_time) = self.start_instant {
             start_time.elapsed().as_secs_f64()
         } else {
             0.0
-        }
+        };
+        self.clamp_time(elapsed)
+    }
+    pub fn duration(&self) -> Option<f64> {
+        self.duration
+    }
+    pub fn at_end(&self) -> bool {
+        self.duration
+            .is_some_and(|duration| self.elapsed() >= duration)
+    }
+    fn clamp_time(&self, time: f64) -> f64 {
+        let time = time.max(0.0);
+        self.duration.map_or(time, |duration| time.min(duration))
     }
     pub fn pause(&mut self) {
         if let Some(controls) = &self.controller {
-            controls.0.set(-1.0);
+            controls.set_paused(true);
+        }
+        if self.start_instant.is_some() {
             self.paused_time = Some(self.elapsed());
         }
     }
     pub fn unpause(&mut self) {
         if let Some(controls) = &self.controller {
-            controls.0.set(1.0);
-            self.start_instant =
-                Some(Instant::now() - Duration::from_secs_f64(self.paused_time.unwrap()));
-            self.paused_time = None;
+            controls.set_paused(false);
+        }
+        if let Some(paused_time) = self.paused_time.take() {
+            self.start_instant = Some(Instant::now() - Duration::from_secs_f64(paused_time));
         }
     }
     pub fn toggle_pause(&mut self) {
@@ -100,36 +146,107 @@ impl PlaybackResource {
         }
     }
     pub fn set_time(&mut self, time: f64) {
+        if self.start_instant.is_none() {
+            return;
+        }
+        let time = self.clamp_time(time);
         if let Some(controls) = &self.controller {
-            controls.1.set(time);
-            self.start_instant = Some(Instant::now() - Duration::from_secs_f64(time));
-            if self.paused_time.is_some() {
-                self.paused_time = Some(time);
-            }
+            controls.seek(time);
+        }
+        self.start_instant = Some(Instant::now() - Duration::from_secs_f64(time));
+        if self.paused_time.is_some() {
+            self.paused_time = Some(time);
         }
     }
+    pub fn stop(&mut self) {
+        if let Some(controls) = self.controller.take() {
+            controls.stop();
+        }
+    }
+    pub fn volume(&self) -> Option<f64> {
+        self.controller.as_ref().map(PlayerControls::volume)
+    }
     pub fn mul_volume(&self, factor: f64) {
         if let Some(controls) = &self.controller {
-            let new_volume = controls.2.value() * factor;
-            controls.2.set(new_volume.max(0.0))
+            controls.set_volume((controls.volume() * factor).max(0.0));
         }
     }
 }
 
-pub fn start_playback(mut playback: ResMut<PlaybackResource>, data: Res<WaveResource>) {
-    let data = data.master.clone();
+impl Drop for PlaybackResource {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+/// Starts the clock without sound, for exports that set the time of every frame themselves and
+/// for hosts that didn't ask for audio output.
+pub fn start_silent(mut playback: ResMut<PlaybackResource>, data: Res<WaveResource>) {
+    playback.duration = Some(data.duration());
+    playback.start_instant = Some(Instant::now());
+    if playback.resume_time > 0.0 {
+        let time = playback.resume_time;
+        playback.set_time(time);
+    }
+}
+
+pub fn start_playback(
+    mut playback: ResMut<PlaybackResource>,
+    data: Res<WaveResource>,
+    errors: &mut ErrorLog,
+) {
+    let master = data.master();
     let sample_rate = playback.sample_rate;
+    playback.duration = Some(master.len() as f64 / sample_rate);
 
     let (tx, rx) = channel();
-
+    let (error_tx, error_rx) = channel();
     thread::spawn(move || {
-        play_and_save(data, sample_rate, "./output/output.wav".into(), tx).unwrap();
+        if let Err(e) = play(master, sample_rate, tx) {
+            eprintln!("Playback failed: {e}");
+            let _ = error_tx.send(e);
+        }
     });
 
-    let (start_instant, controls) = rx.recv().unwrap();
+    // If the device is missing or can't play the song, the sender is dropped unused, so keep the
+    // visuals running on the clock.
+    match rx.recv() {
+        Ok((start_instant, controls)) => {
+            playback.start_instant = Some(start_instant);
+            playback.controller = Some(controls);
+        }
+        Err(_) => {
+            playback.start_instant = Some(Instant::now());
+            let error = error_rx
+                .recv()
+                .unwrap_or_else(|_| AppError::NoAudioDevice("playback stopped".to_string()));
+            errors.push(error);
+        }
+    }
+    if playback.resume_time > 0.0 {
+        let time = playback.resume_time;
+        playback.set_time(time);
+    }
+}
+
+/// Saves `master` to `path` in the background.
+pub fn record(master: SampleBuffer<Frame>, sample_rate: f64, path: PathBuf, errors: &mut ErrorLog) {
+    if let Some(dir) = path.parent() {
+        if let Err(e) = std::fs::create_dir_all(dir) {
+            return errors.push(AppError::OutputNotWritable(path, e.to_string()));
+        }
+    }
+    thread::spawn(move || {
+        if let Err(e) = save_wav(&master, sample_rate, &path) {
+            eprintln!("Could not save {}: {e}", path.display());
+        }
+    });
+}
 
-    playback.start_instant = Some(start_instant);
-    playback.controller = Some(controls);
+fn loop_playback(mut playback: ResMut<PlaybackResource>, end_behavior: Res<EndBehavior>) {
+    if *end_behavior == EndBehavior::Loop && playback.at_end() {
+        playback.set_time(0.0);
+    }
 }
 
 fn handle_pause_playback(
@@ -146,18 +263,58 @@ fn handle_pause_playback(
             }
             if event.key_code == KeyCode::ArrowUp {
                 playback.mul_volume(1.5);
-                info!(
-                    "Volume: {}",
-                    playback.controller.as_ref().unwrap().2.value()
-                );
+                if let Some(volume) = playback.volume() {
+                    info!("Volume: {volume}");
+                }
             }
             if event.key_code == KeyCode::ArrowDown {
                 playback.mul_volume(1.0 / 1.5);
-                info!(
-                    "Volume: {}",
-                    playback.controller.as_ref().unwrap().2.value()
-                );
+                if let Some(volume) = playback.volume() {
+                    info!("Volume: {volume}");
+                }
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn started(duration: f64) -> PlaybackResource {
+        let mut playback = PlaybackResource::new(44100.0);
+        playback.start_instant = Some(Instant::now());
+        playback.duration = Some(duration);
+        playback
+    }
+
+    #[test]
+    fn set_time_clamps_to_the_song() {
+        let mut playback = started(10.0);
+        playback.set_time(100.0);
+        assert_eq!(playback.elapsed(), 10.0);
+        assert!(playback.at_end());
+
+        playback.set_time(-5.0);
+        assert!(playback.elapsed() < 0.1);
+        assert!(!playback.at_end());
+    }
+
+    #[test]
+    fn paused_seek_past_the_end_stays_at_the_end() {
+        let mut playback = started(10.0);
+        playback.pause();
+        playback.set_time(25.0);
+        assert_eq!(playback.elapsed(), 10.0);
+        playback.unpause();
+        assert_eq!(playback.elapsed(), 10.0);
+    }
+
+    #[test]
+    fn seeking_before_playback_starts_is_ignored() {
+        let mut playback = PlaybackResource::new(44100.0);
+        playback.set_time(5.0);
+        assert_eq!(playback.elapsed(), 0.0);
+        assert!(!playback.at_end());
+    }
+}